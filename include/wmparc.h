@@ -0,0 +1,91 @@
+/*
+ * C interface of wmparc, the white matter parcellation by the cortex labels of tractography streamlines.
+ * Link against the shared library built by `cargo build --release` (target/release/libwmparc.so, .dylib or .dll).
+ *
+ * Volumes are passed with x running fastest (the order of the NIfTI data), affines as 16 values in row-major order
+ * and streamlines as x, y, z triplets in RAS (mm). All functions returning int return WMPARC_OK or a negative error code.
+ */
+#ifndef WMPARC_H
+#define WMPARC_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define WMPARC_OK                0
+#define WMPARC_ERROR_ARGUMENT   -1  /* Invalid argument, e.g. a null pointer or an unknown option. */
+#define WMPARC_ERROR_NO_VOLUME  -2  /* No label volume was set. */
+#define WMPARC_ERROR_WEIGHTS    -3  /* The number of weights does not match the number of streamlines. */
+#define WMPARC_ERROR_FAILED     -4  /* The parcellation failed, see wmparc_last_error. */
+
+/* Options of wmparc_set_option */
+#define WMPARC_OPTION_KERNEL      0  /* WMPARC_KERNEL_* */
+#define WMPARC_OPTION_ENDPOINT    1  /* WMPARC_ENDPOINT_* */
+#define WMPARC_OPTION_TIE_BREAK   2  /* WMPARC_TIE_BREAK_* */
+#define WMPARC_OPTION_HEMISPHERE  3  /* 0 or 1, reject votes of cortex labels from the other hemisphere. */
+#define WMPARC_OPTION_WM_OFFSET   4  /* 0 or 1, renumber the labels by 2000 (1001 -> 3001). */
+#define WMPARC_OPTION_MERGE       5  /* 0 or 1, write the labels into a copy of the label volume, implies wm offset. */
+#define WMPARC_OPTION_LABELWM     6  /* 0 or 1, label like mri_aparc2aseg --labelwm, implies merge and wm offset. */
+#define WMPARC_OPTION_THREADS     7  /* Number of threads > 0, by default one per core. */
+
+#define WMPARC_KERNEL_NONE        0
+#define WMPARC_KERNEL_OCTANT      1  /* Default */
+#define WMPARC_KERNEL_FULL        2
+
+#define WMPARC_ENDPOINT_FIRST     0
+#define WMPARC_ENDPOINT_LAST      1  /* Default */
+
+#define WMPARC_TIE_BREAK_LOWEST   0  /* Default */
+#define WMPARC_TIE_BREAK_VOTES    1
+#define WMPARC_TIE_BREAK_ENDPOINT 2
+
+typedef struct WmparcContext WmparcContext;
+
+/* Version of the library */
+const char *wmparc_version(void);
+
+/* Create a context with the default options and an identity affine, free it with wmparc_free. */
+WmparcContext *wmparc_create(void);
+void wmparc_free(WmparcContext *ctx);
+
+/* Set the label volume (e.g. aparc+aseg) of nx * ny * nz values, the values are copied. */
+int wmparc_set_volume(WmparcContext *ctx, const float *data, size_t nx, size_t ny, size_t nz);
+
+/* Set the voxel to RAS affine of the label volume (row-major), WMPARC_ERROR_ARGUMENT if it is not invertible. */
+int wmparc_set_affine(WmparcContext *ctx, const float affine[16]);
+
+/* Add a streamline of n points (3 * n values), the points are copied. */
+int wmparc_push_streamline(WmparcContext *ctx, const float *points, size_t n);
+
+/* Remove all streamlines and weights. */
+int wmparc_clear_streamlines(WmparcContext *ctx);
+
+/* Set one weight per streamline (e.g. of SIFT2 or COMMIT), a null pointer removes the weights. */
+int wmparc_set_weights(WmparcContext *ctx, const float *weights, size_t n);
+
+/* Set an option (WMPARC_OPTION_*) to a value, WMPARC_ERROR_ARGUMENT for unknown options or invalid values. */
+int wmparc_set_option(WmparcContext *ctx, int option, int value);
+
+/* Only label the white matter between min and max mm from the cortex. */
+int wmparc_set_depth_band(WmparcContext *ctx, float min, float max);
+
+/* Run the parcellation of the pushed streamlines. */
+int wmparc_run(WmparcContext *ctx);
+
+/* Output labels of the last run in the grid of the label volume, len is set to the number of values.
+ * The buffer belongs to the context and is valid until the next run or wmparc_free. */
+const float *wmparc_output(const WmparcContext *ctx, size_t *len);
+
+/* Statistics of the last run: streamlines, labelled streamlines, votes, rejected votes and labelled voxels. */
+int wmparc_stats(const WmparcContext *ctx, size_t stats[5]);
+
+/* Error message of the last call that changed the context, empty if it succeeded. The string is valid until the next call with the context. */
+const char *wmparc_last_error(const WmparcContext *ctx);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
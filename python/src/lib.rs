@@ -0,0 +1,180 @@
+//Python bindings of the white matter parcellation
+//Volumes are numpy arrays indexed (x, y, z) together with a 4x4 voxel to RAS affine as in nibabel,
+//streamlines are (N, 3) arrays of RAS coordinates in mm as in nibabel.streamlines and dipy.
+use std::panic;
+
+use numpy::ndarray::{Array2, Array3, ArrayD, IxDyn};
+use numpy::{AllowTypeChange, IntoPyArray, PyArray2, PyArray3, PyArrayDyn, PyArrayLike1, PyArrayLike2, PyArrayLike3};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use wmparc::nifti::NIfTI1Data;
+use wmparc::{nifti, trackvis, tractogram, volume};
+use wmparc::{EndpointRule, Kernel, Parcellation, TieBreak};
+
+type Affine = [[f32; 4]; 4];
+
+//Convert a 4x4 array to an affine
+fn to_affine(array: &PyArrayLike2<f32, AllowTypeChange>) -> PyResult<Affine> {
+    let array = array.as_array();
+    if array.shape() != [4, 4] {
+        return Err(PyValueError::new_err("The affine has to be a 4x4 array."));
+    }
+    let mut affine = [[0.0f32; 4]; 4];
+    for (r, row) in affine.iter_mut().enumerate() {
+        for (c, value) in row.iter_mut().enumerate() {
+            *value = array[[r, c]];
+        }
+    }
+    Ok(affine)
+}
+
+fn affine_array<'py>(py: Python<'py>, affine: &Affine) -> Bound<'py, PyArray2<f32>> {
+    Array2::from_shape_fn((4, 4), |(r, c)| affine[r][c]).into_pyarray(py)
+}
+
+//Read a NIfTI (.nii) or FreeSurfer (.mgh, .mgz) volume.
+//Returns the data as float32 array indexed (x, y, z) or (x, y, z, t) and the voxel to RAS affine.
+#[pyfunction]
+fn read_nifti<'py>(py: Python<'py>, path: &str) -> (Bound<'py, PyArrayDyn<f32>>, Bound<'py, PyArray2<f32>>) {
+    let (nheader, ndata) = volume::read(path);
+    let frames = ndata.len();
+    let (nz, ny, nx) = (ndata[0].len(), ndata[0][0].len(), ndata[0][0][0].len());
+
+    let shape = if frames > 1 { vec![nx, ny, nz, frames] } else { vec![nx, ny, nz] };
+    let data = ArrayD::from_shape_fn(IxDyn(&shape), |i| {
+        let t = if frames > 1 { i[3] } else { 0 };
+        ndata[t][i[2]][i[1]][i[0]]
+    });
+    (data.into_pyarray(py), affine_array(py, &nifti::affine(&nheader)))
+}
+
+//Read a TrackVis (.trk) file.
+//Returns the streamlines as (N, 3) float32 arrays of RAS coordinates in mm and the voxel to RAS affine of the header.
+#[pyfunction]
+fn read_trk<'py>(py: Python<'py>, path: &str) -> (Vec<Bound<'py, PyArray2<f32>>>, Bound<'py, PyArray2<f32>>) {
+    let (header, tracks) = trackvis::read_tracks(path);
+    let voxmm_to_ras = trackvis::voxmm_to_ras(&header);
+
+    let streamlines = tracks.iter().map(|track| {
+        Array2::from_shape_fn((track.points.len(), 3), |(i, c)| {
+            nifti::apply_affine(&voxmm_to_ras, track.points[i])[c]
+        }).into_pyarray(py)
+    }).collect();
+
+    //The affine maps voxel centers, not the voxmm coordinates
+    let mut affine = voxmm_to_ras;
+    for row in affine.iter_mut().take(3) {
+        for c in 0..3 {
+            row[c] *= header.voxel_size[c];
+            row[3] += 0.5 * row[c];
+        }
+    }
+    (streamlines, affine_array(py, &affine))
+}
+
+//Label the white matter by the cortex labels of the streamlines.
+//labels is the cortex parcellation (e.g. aparc+aseg) indexed (x, y, z) with its voxel to RAS affine,
+//the streamlines are (N, 3) arrays of RAS coordinates in mm. Returns the white matter labels indexed (x, y, z).
+#[pyfunction]
+#[pyo3(signature = (labels, affine, streamlines, weights=None, hemisphere=false, kernel="octant", endpoint="last",
+                    tie_break="lowest", wm_offset=false, merge=false, labelwm=false, depth_min=0.0,
+                    depth_max=f32::INFINITY, threads=None))]
+#[allow(clippy::too_many_arguments)]
+fn parcellate<'py>(py: Python<'py>, labels: PyArrayLike3<'py, f32, AllowTypeChange>, affine: PyArrayLike2<'py, f32, AllowTypeChange>,
+                   streamlines: Vec<PyArrayLike2<'py, f32, AllowTypeChange>>, weights: Option<PyArrayLike1<'py, f32, AllowTypeChange>>,
+                   hemisphere: bool, kernel: &str, endpoint: &str, tie_break: &str, wm_offset: bool, merge: bool, labelwm: bool,
+                   depth_min: f32, depth_max: f32, threads: Option<usize>) -> PyResult<Bound<'py, PyArray3<f32>>> {
+    let kernel = match kernel {
+        "octant" => Kernel::Octant,
+        "none" => Kernel::None,
+        "full" => Kernel::Full,
+        _ => return Err(PyValueError::new_err("kernel has to be none, octant or full.")),
+    };
+    let endpoint_rule = match endpoint {
+        "last" => EndpointRule::Last,
+        "first" => EndpointRule::First,
+        _ => return Err(PyValueError::new_err("endpoint has to be first or last.")),
+    };
+    let tie_break = match tie_break {
+        "lowest" => TieBreak::Lowest,
+        "votes" => TieBreak::Votes,
+        "endpoint" => TieBreak::Endpoint,
+        _ => return Err(PyValueError::new_err("tie_break has to be lowest, votes or endpoint.")),
+    };
+    if !(depth_min >= 0.0 && depth_max >= depth_min) {
+        return Err(PyValueError::new_err("The depth band has to be 0 <= depth_min <= depth_max."));
+    }
+
+    //Image header and data in the layout of the library
+    let affine = to_affine(&affine)?;
+    let det = nifti::determinant(&affine);
+    if det == 0.0 || !det.is_finite() || affine.iter().flatten().any(|v| !v.is_finite()) {
+        return Err(PyValueError::new_err("The affine is not invertible."));
+    }
+    let labels = labels.as_array();
+    let (nx, ny, nz) = labels.dim();
+    if nx == 0 || ny == 0 || nz == 0 {
+        return Err(PyValueError::new_err("The label volume is empty."));
+    }
+    if nx > u16::MAX as usize || ny > u16::MAX as usize || nz > u16::MAX as usize {
+        return Err(PyValueError::new_err("The label volume is too large."));
+    }
+    let nheader = nifti::header([nx as u16, ny as u16, nz as u16, 1], &affine);
+    let ndata: NIfTI1Data = vec![(0..nz).map(|z| {
+        (0..ny).map(|y| (0..nx).map(|x| labels[[x, y, z]]).collect()).collect()
+    }).collect()];
+
+    let ras_to_vox = nifti::inverse_affine(&affine);
+    let mut tracts = Vec::with_capacity(streamlines.len());
+    for streamline in streamlines.iter() {
+        let points = streamline.as_array();
+        if points.ncols() != 3 {
+            return Err(PyValueError::new_err("Every streamline has to be an (N, 3) array."));
+        }
+        let points: Vec<[f32; 3]> = points.rows().into_iter().map(|p| [p[0], p[1], p[2]]).collect();
+        tracts.push(tractogram::streamline_from_ras(&points, &ras_to_vox));
+    }
+
+    let weights = weights.map(|w| w.as_array().to_vec());
+    if let Some(ref w) = weights {
+        if w.len() != tracts.len() {
+            return Err(PyValueError::new_err(format!("{} weights given for {} streamlines.", w.len(), tracts.len())));
+        }
+    }
+
+    let mut parcellation = Parcellation::new()
+        .kernel(kernel)
+        .endpoint_rule(endpoint_rule)
+        .tie_break(tie_break)
+        .hemisphere(hemisphere)
+        .depth_band(depth_min, depth_max)
+        .wm_offset(wm_offset)
+        .merge(merge)
+        .labelwm(labelwm);
+    if let Some(threads) = threads {
+        parcellation = parcellation.threads(threads);
+    }
+
+    //Remaining panics of the library are raised as ValueError instead of PanicException
+    let result = py.detach(|| panic::catch_unwind(panic::AssertUnwindSafe(|| parcellation.run(&nheader, &ndata, &tracts, weights.as_deref()))));
+    let result = match result {
+        Ok(result) => result,
+        Err(why) => {
+            let message = why.downcast_ref::<String>().cloned()
+                .or_else(|| why.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "The parcellation failed.".to_string());
+            return Err(PyValueError::new_err(message));
+        },
+    };
+    let output = Array3::from_shape_fn((nx, ny, nz), |(x, y, z)| result.volume[0][z][y][x]);
+    Ok(output.into_pyarray(py))
+}
+
+#[pymodule]
+fn pywmparc(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(read_nifti, m)?)?;
+    m.add_function(wrap_pyfunction!(read_trk, m)?)?;
+    m.add_function(wrap_pyfunction!(parcellate, m)?)?;
+    Ok(())
+}
@@ -0,0 +1,104 @@
+"""Tests of the Python bindings on a tiny synthetic volume, run with pytest after `maturin develop`."""
+import struct
+
+import numpy as np
+import pytest
+
+import pywmparc
+
+AFFINE = np.array([[-2.0, 0.0, 0.0, 10.0],
+                   [0.0, 2.0, 0.0, -4.0],
+                   [0.0, 0.0, 2.0, -2.0],
+                   [0.0, 0.0, 0.0, 1.0]], dtype=np.float32)
+VOXEL_SIZE = (2.0, 2.0, 2.0)
+
+# A row of voxels along x: cortex (1003), three white matter voxels (2) and background
+LABELS = np.zeros((5, 2, 2), dtype=np.float32)
+LABELS[0, 0, 0] = 1003
+LABELS[1:4, 0, 0] = 2
+
+# Voxel centers of the row from the cortex to the last white matter voxel
+VOXELS = np.array([[x, 0.0, 0.0] for x in range(4)], dtype=np.float32)
+RAS = VOXELS @ AFFINE[:3, :3].T + AFFINE[:3, 3]
+
+
+def write_nifti(path, data, affine):
+    """Write a float32 NIfTI-1 file with the affine as sform."""
+    header = bytearray(352)
+    struct.pack_into("<i", header, 0, 348)
+    struct.pack_into("<8h", header, 40, 3, *data.shape, 1, 1, 1, 1)
+    struct.pack_into("<2h", header, 70, 16, 32)
+    struct.pack_into("<8f", header, 76, 1.0, *VOXEL_SIZE, 1.0, 1.0, 1.0, 1.0)
+    struct.pack_into("<2f", header, 108, 352.0, 1.0)
+    struct.pack_into("<2h", header, 252, 0, 1)
+    struct.pack_into("<12f", header, 280, *affine[:3].flatten())
+    header[344:348] = b"n+1\0"
+    with open(path, "wb") as f:
+        f.write(bytes(header))
+        f.write(data.astype("<f4").tobytes(order="F"))
+
+
+def write_trk(path, streamlines, dim, affine):
+    """Write a TrackVis file of streamlines given in voxel coordinates."""
+    header = bytearray(1000)
+    header[0:6] = b"TRACK\0"
+    struct.pack_into("<3h", header, 6, *dim)
+    struct.pack_into("<3f", header, 12, *VOXEL_SIZE)
+    struct.pack_into("<16f", header, 440, *affine.flatten())
+    header[948:952] = b"LAS\0"
+    struct.pack_into("<3i", header, 988, len(streamlines), 2, 1000)
+    with open(path, "wb") as f:
+        f.write(bytes(header))
+        for streamline in streamlines:
+            # TrackVis stores mm from the corner of the first voxel
+            voxmm = (streamline + 0.5) * np.array(VOXEL_SIZE, dtype=np.float32)
+            f.write(struct.pack("<i", len(voxmm)))
+            f.write(voxmm.astype("<f4").tobytes())
+
+
+def test_read_nifti(tmp_path):
+    path = str(tmp_path / "labels.nii")
+    write_nifti(path, LABELS, AFFINE)
+
+    data, affine = pywmparc.read_nifti(path)
+    assert data.shape == LABELS.shape
+    assert np.array_equal(data, LABELS)
+    assert np.allclose(affine, AFFINE)
+
+
+def test_read_trk(tmp_path):
+    path = str(tmp_path / "tracts.trk")
+    write_trk(path, [VOXELS, VOXELS[:2]], LABELS.shape, AFFINE)
+
+    streamlines, affine = pywmparc.read_trk(path)
+    assert len(streamlines) == 2
+    assert np.allclose(streamlines[0], RAS)
+    assert np.allclose(streamlines[1], RAS[:2])
+    assert np.allclose(affine, AFFINE)
+
+
+def test_parcellate():
+    wm = pywmparc.parcellate(LABELS, AFFINE, [RAS])
+    expected = np.zeros_like(LABELS)
+    expected[1:4, 0, 0] = 1003
+    assert wm.shape == LABELS.shape
+    assert np.array_equal(wm, expected)
+
+    offset = pywmparc.parcellate(LABELS, AFFINE, [RAS], wm_offset=True)
+    assert np.array_equal(offset, np.where(expected > 0, expected + 2000, 0))
+
+
+def test_parcellate_rejects_invalid_arguments():
+    with pytest.raises(ValueError):
+        pywmparc.parcellate(LABELS, AFFINE, [RAS], weights=[1.0, 2.0])
+    with pytest.raises(ValueError):
+        pywmparc.parcellate(LABELS, AFFINE, [RAS], depth_min=5.0, depth_max=2.0)
+    with pytest.raises(ValueError):
+        pywmparc.parcellate(LABELS, AFFINE, [RAS], kernel="cube")
+
+
+def test_parcellate_rejects_singular_affine():
+    with pytest.raises(ValueError):
+        pywmparc.parcellate(LABELS, np.zeros((4, 4), dtype=np.float32), [RAS])
+    with pytest.raises(ValueError):
+        pywmparc.parcellate(np.zeros((0, 2, 2), dtype=np.float32), AFFINE, [RAS])
@@ -0,0 +1,356 @@
+//C interface of the parcellation, declared in include/wmparc.h
+//A context collects the label volume, its affine and the streamlines, runs the parcellation and keeps the output buffer.
+//Volumes are passed with x running fastest (the order of the NIfTI data), streamlines as x, y, z triplets in RAS (mm).
+//All pointers have to be valid for the given lengths, the functions are unsafe to call with invalid pointers.
+#![allow(clippy::missing_safety_doc)]
+use std::ffi::CString;
+use std::mem;
+use std::os::raw::{c_char, c_float, c_int};
+use std::panic;
+use std::ptr;
+use std::slice;
+
+use nifti;
+use nifti::NIfTI1Data;
+use tractogram;
+use {EndpointRule, Kernel, Parcellation, Stats, TieBreak};
+
+pub const WMPARC_OK: c_int = 0;
+pub const WMPARC_ERROR_ARGUMENT: c_int = -1;   //Invalid argument, e.g. a null pointer or an unknown option.
+pub const WMPARC_ERROR_NO_VOLUME: c_int = -2;  //No label volume was set.
+pub const WMPARC_ERROR_WEIGHTS: c_int = -3;    //The number of weights does not match the number of streamlines.
+pub const WMPARC_ERROR_FAILED: c_int = -4;     //The parcellation failed, see wmparc_last_error.
+
+//Options of wmparc_set_option, the values of the kernel, endpoint and tie-break options are listed in wmparc.h
+pub const WMPARC_OPTION_KERNEL: c_int = 0;
+pub const WMPARC_OPTION_ENDPOINT: c_int = 1;
+pub const WMPARC_OPTION_TIE_BREAK: c_int = 2;
+pub const WMPARC_OPTION_HEMISPHERE: c_int = 3;
+pub const WMPARC_OPTION_WM_OFFSET: c_int = 4;
+pub const WMPARC_OPTION_MERGE: c_int = 5;
+pub const WMPARC_OPTION_LABELWM: c_int = 6;
+pub const WMPARC_OPTION_THREADS: c_int = 7;
+
+pub struct WmparcContext {
+    parcellation: Parcellation,
+    dim: [usize; 3],
+    data: Option<NIfTI1Data>,
+    affine: [[f32; 4]; 4],
+    streamlines: Vec<Vec<[f32; 3]>>,
+    weights: Option<Vec<f32>>,
+    output: Vec<f32>,
+    stats: Option<Stats>,
+    error: CString,
+}
+
+impl WmparcContext {
+    fn fail(&mut self, code: c_int, message: &str) -> c_int {
+        self.error = CString::new(message.replace('\0', "")).unwrap_or_default();
+        code
+    }
+
+    //Apply a builder method to the parcellation
+    fn set<F: FnOnce(Parcellation) -> Parcellation>(&mut self, f: F) {
+        let parcellation = mem::take(&mut self.parcellation);
+        self.parcellation = f(parcellation);
+    }
+}
+
+//Return the context of a call, the error of the previous call is cleared
+unsafe fn context<'a>(ctx: *mut WmparcContext) -> Option<&'a mut WmparcContext> {
+    let ctx = ctx.as_mut()?;
+    ctx.error = CString::default();
+    Some(ctx)
+}
+
+//Return the version of the library
+#[no_mangle]
+pub extern "C" fn wmparc_version() -> *const c_char {
+    concat!(env!("CARGO_PKG_VERSION"), "\0").as_ptr() as *const c_char
+}
+
+//Create a context with the default options and an identity affine, free it with wmparc_free
+#[no_mangle]
+pub extern "C" fn wmparc_create() -> *mut WmparcContext {
+    let context = WmparcContext {
+        parcellation: Parcellation::new(),
+        dim: [0; 3],
+        data: None,
+        affine: [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]],
+        streamlines: Vec::new(),
+        weights: None,
+        output: Vec::new(),
+        stats: None,
+        error: CString::default(),
+    };
+    Box::into_raw(Box::new(context))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_free(ctx: *mut WmparcContext) {
+    if !ctx.is_null() {
+        drop(Box::from_raw(ctx));
+    }
+}
+
+//Set the label volume (e.g. aparc+aseg) of nx * ny * nz values with x running fastest
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_set_volume(ctx: *mut WmparcContext, data: *const c_float, nx: usize, ny: usize, nz: usize) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    if data.is_null() || nx == 0 || ny == 0 || nz == 0 {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The volume is empty");
+    }
+    if nx > u16::MAX as usize || ny > u16::MAX as usize || nz > u16::MAX as usize {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The volume is too large");
+    }
+
+    let values = slice::from_raw_parts(data, nx * ny * nz);
+    ctx.data = Some(vec![values.chunks(nx * ny).map(|slice| slice.chunks(nx).map(|row| row.to_vec()).collect()).collect()]);
+    ctx.dim = [nx, ny, nz];
+    WMPARC_OK
+}
+
+//Set the voxel to RAS affine of the label volume as 16 values in row-major order, it has to be invertible
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_set_affine(ctx: *mut WmparcContext, affine: *const c_float) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    if affine.is_null() {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The affine is null");
+    }
+
+    let values = slice::from_raw_parts(affine, 16);
+    let mut matrix = [[0.0f32; 4]; 4];
+    for (r, row) in matrix.iter_mut().enumerate() {
+        row.copy_from_slice(&values[r * 4..r * 4 + 4]);
+    }
+    let det = nifti::determinant(&matrix);
+    if det == 0.0 || !det.is_finite() || matrix.iter().flatten().any(|v| !v.is_finite()) {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The affine is not invertible");
+    }
+    ctx.affine = matrix;
+    WMPARC_OK
+}
+
+//Add a streamline of n points given as x, y, z triplets in RAS (mm)
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_push_streamline(ctx: *mut WmparcContext, points: *const c_float, n: usize) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    if points.is_null() && n > 0 {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The points are null");
+    }
+
+    let values = if n > 0 { slice::from_raw_parts(points, n * 3) } else { &[] };
+    ctx.streamlines.push(values.chunks(3).map(|p| [p[0], p[1], p[2]]).collect());
+    WMPARC_OK
+}
+
+//Remove all streamlines and weights
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_clear_streamlines(ctx: *mut WmparcContext) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    ctx.streamlines.clear();
+    ctx.weights = None;
+    WMPARC_OK
+}
+
+//Set one weight per streamline, a null pointer removes the weights
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_set_weights(ctx: *mut WmparcContext, weights: *const c_float, n: usize) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    ctx.weights = if weights.is_null() { None } else { Some(slice::from_raw_parts(weights, n).to_vec()) };
+    WMPARC_OK
+}
+
+//Set an option to a value, see WMPARC_OPTION_* in wmparc.h
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_set_option(ctx: *mut WmparcContext, option: c_int, value: c_int) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+
+    match (option, value) {
+        (WMPARC_OPTION_KERNEL, 0) => ctx.set(|p| p.kernel(Kernel::None)),
+        (WMPARC_OPTION_KERNEL, 1) => ctx.set(|p| p.kernel(Kernel::Octant)),
+        (WMPARC_OPTION_KERNEL, 2) => ctx.set(|p| p.kernel(Kernel::Full)),
+        (WMPARC_OPTION_ENDPOINT, 0) => ctx.set(|p| p.endpoint_rule(EndpointRule::First)),
+        (WMPARC_OPTION_ENDPOINT, 1) => ctx.set(|p| p.endpoint_rule(EndpointRule::Last)),
+        (WMPARC_OPTION_TIE_BREAK, 0) => ctx.set(|p| p.tie_break(TieBreak::Lowest)),
+        (WMPARC_OPTION_TIE_BREAK, 1) => ctx.set(|p| p.tie_break(TieBreak::Votes)),
+        (WMPARC_OPTION_TIE_BREAK, 2) => ctx.set(|p| p.tie_break(TieBreak::Endpoint)),
+        (WMPARC_OPTION_HEMISPHERE, _) => ctx.set(|p| p.hemisphere(value != 0)),
+        (WMPARC_OPTION_WM_OFFSET, _) => ctx.set(|p| p.wm_offset(value != 0)),
+        (WMPARC_OPTION_MERGE, _) => ctx.set(|p| p.merge(value != 0)),
+        (WMPARC_OPTION_LABELWM, _) => ctx.set(|p| p.labelwm(value != 0)),
+        (WMPARC_OPTION_THREADS, _) if value > 0 => ctx.set(|p| p.threads(value as usize)),
+        _ => return ctx.fail(WMPARC_ERROR_ARGUMENT, &format!("Invalid value {} of option {}", value, option)),
+    }
+    WMPARC_OK
+}
+
+//Only label the white matter between min and max mm from the cortex
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_set_depth_band(ctx: *mut WmparcContext, min: c_float, max: c_float) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    if min.is_nan() || max.is_nan() || min < 0.0 || max < min {
+        return ctx.fail(WMPARC_ERROR_ARGUMENT, "The depth band has to be 0 <= min <= max");
+    }
+    ctx.set(|p| p.depth_band(min, max));
+    WMPARC_OK
+}
+
+//Run the parcellation of the pushed streamlines
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_run(ctx: *mut WmparcContext) -> c_int {
+    let ctx = match context(ctx) {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    if let Some(ref weights) = ctx.weights {
+        if weights.len() != ctx.streamlines.len() {
+            let message = format!("Expected {} weights, but got {} weights", ctx.streamlines.len(), weights.len());
+            return ctx.fail(WMPARC_ERROR_WEIGHTS, &message);
+        }
+    }
+    if ctx.data.is_none() {
+        return ctx.fail(WMPARC_ERROR_NO_VOLUME, "No label volume was set");
+    }
+
+    let [nx, ny, nz] = ctx.dim;
+    let affine = ctx.affine;
+    let streamlines = &ctx.streamlines;
+    let parcellation = &ctx.parcellation;
+    let data = ctx.data.as_ref().unwrap();
+    let weights = ctx.weights.as_deref();
+
+    //Panics must not unwind into C
+    let result = panic::catch_unwind(panic::AssertUnwindSafe(|| {
+        let nheader = nifti::header([nx as u16, ny as u16, nz as u16, 1], &affine);
+        let ras_to_vox = nifti::inverse_affine(&affine);
+        let tracts: Vec<_> = streamlines.iter().map(|s| tractogram::streamline_from_ras(s, &ras_to_vox)).collect();
+        parcellation.run(&nheader, data, &tracts, weights)
+    }));
+    match result {
+        Ok(result) => {
+            ctx.output = result.volume[0].iter().flat_map(|slice| slice.iter().flat_map(|row| row.iter().cloned())).collect();
+            ctx.stats = Some(result.stats);
+            WMPARC_OK
+        },
+        Err(why) => {
+            let message = why.downcast_ref::<String>().cloned()
+                .or_else(|| why.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "The parcellation failed".to_string());
+            ctx.fail(WMPARC_ERROR_FAILED, &message)
+        },
+    }
+}
+
+//Return the output labels of the last run in the grid of the label volume (x running fastest) and their number in len.
+//The buffer belongs to the context and is valid until the next run or wmparc_free.
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_output(ctx: *const WmparcContext, len: *mut usize) -> *const c_float {
+    let ctx = match ctx.as_ref() {
+        Some(ctx) => ctx,
+        None => return ptr::null(),
+    };
+    if let Some(len) = len.as_mut() {
+        *len = ctx.output.len();
+    }
+    if ctx.output.is_empty() { ptr::null() } else { ctx.output.as_ptr() }
+}
+
+//Write the statistics of the last run: streamlines, labelled streamlines, votes, rejected votes and labelled voxels
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_stats(ctx: *const WmparcContext, stats: *mut usize) -> c_int {
+    let ctx = match ctx.as_ref() {
+        Some(ctx) => ctx,
+        None => return WMPARC_ERROR_ARGUMENT,
+    };
+    match (ctx.stats.as_ref(), stats.is_null()) {
+        (Some(s), false) => {
+            let values = [s.tracts, s.labelled_tracts, s.votes, s.rejected_votes, s.labelled_voxels];
+            ptr::copy_nonoverlapping(values.as_ptr(), stats, values.len());
+            WMPARC_OK
+        },
+        _ => WMPARC_ERROR_ARGUMENT,
+    }
+}
+
+//Return the error message of the last call that changed the context, empty if it succeeded
+#[no_mangle]
+pub unsafe extern "C" fn wmparc_last_error(ctx: *const WmparcContext) -> *const c_char {
+    match ctx.as_ref() {
+        Some(ctx) => ctx.error.as_ptr(),
+        None => b"\0".as_ptr() as *const c_char,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CStr;
+
+    #[test]
+    fn run_labels_the_white_matter_of_a_streamline() {
+        //A row of voxels: cortex, three white matter voxels and background
+        let volume = [1003.0, 2.0, 2.0, 2.0, 0.0];
+        let points = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 2.0, 0.0, 0.0, 3.0, 0.0, 0.0];
+        unsafe {
+            let ctx = wmparc_create();
+            assert_eq!(wmparc_set_volume(ctx, volume.as_ptr(), 5, 1, 1), WMPARC_OK);
+            assert_eq!(wmparc_push_streamline(ctx, points.as_ptr(), 4), WMPARC_OK);
+            assert_eq!(wmparc_run(ctx), WMPARC_OK);
+
+            let mut len = 0;
+            let output = wmparc_output(ctx, &mut len);
+            assert_eq!(slice::from_raw_parts(output, len), &[0.0, 1003.0, 1003.0, 1003.0, 0.0]);
+            let mut stats = [0usize; 5];
+            assert_eq!(wmparc_stats(ctx, stats.as_mut_ptr()), WMPARC_OK);
+            assert_eq!(stats[..2], [1, 1]);
+            wmparc_free(ctx);
+        }
+    }
+
+    #[test]
+    fn error_is_cleared_by_the_next_call() {
+        unsafe {
+            let ctx = wmparc_create();
+            assert_eq!(wmparc_run(ctx), WMPARC_ERROR_NO_VOLUME);
+            assert!(!CStr::from_ptr(wmparc_last_error(ctx)).to_bytes().is_empty());
+            assert_eq!(wmparc_set_option(ctx, WMPARC_OPTION_THREADS, 2), WMPARC_OK);
+            assert!(CStr::from_ptr(wmparc_last_error(ctx)).to_bytes().is_empty());
+            wmparc_free(ctx);
+        }
+    }
+
+    #[test]
+    fn singular_affine_is_rejected() {
+        let zero = [0.0f32; 16];
+        let identity = [1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        unsafe {
+            let ctx = wmparc_create();
+            assert_eq!(wmparc_set_affine(ctx, zero.as_ptr()), WMPARC_ERROR_ARGUMENT);
+            assert!(!CStr::from_ptr(wmparc_last_error(ctx)).to_bytes().is_empty());
+            assert_eq!(wmparc_set_affine(ctx, identity.as_ptr()), WMPARC_OK);
+            wmparc_free(ctx);
+        }
+    }
+}
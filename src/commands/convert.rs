@@ -0,0 +1,45 @@
+//Conversion between the volume and the tractogram formats
+use std::process::exit;
+use getopts::Options;
+use wmparc::{tractogram, volume};
+
+use commands::{parse, print_usage};
+
+pub fn run(program: &str, args: &[String]) {
+    let brief = format!("Usage: {} convert <input> <output> [options]\n  The format is selected by the file extension, \
+                         volumes (.nii, .mgh, .mgz) and tractograms (.trk, .tck, .trx, .vtk, .vtp) can be converted.", program);
+    let mut opts = Options::new();
+    opts.optopt("n", "nifti", "path to the reference image of the tracts, its voxel grid is the reference space of the output [required for tractograms]", "FILE");
+    let matches = parse(&mut opts, args, &brief, 2);
+
+    let input = &matches.free[0];
+    let output = &matches.free[1];
+
+    if volume::is_volume(input) {
+        if !volume::is_volume(output) {
+            println!("A volume can only be converted to a volume (.nii, .mgh, .mgz).");
+            exit(1);
+        }
+        let (header, data) = volume::read(input);
+        volume::write(header, data, output);
+        println!("Converted {} to {}", input, output);
+        return;
+    }
+
+    let nifti_file = match matches.opt_str("n") {
+        Some(s) => s,
+        None => {
+            println!("The reference image (--nifti) is required to convert tractograms.");
+            print_usage(&brief, &opts);
+            exit(1);
+        },
+    };
+    //Fail early on unknown output formats
+    tractogram::output_format(output);
+
+    let (nheader, _) = volume::read(&nifti_file);
+    let (source, streamlines) = tractogram::read_source(input, &nheader);
+    let selection: Vec<usize> = (0..streamlines.len()).collect();
+    tractogram::write_selection(&source, &nheader, &selection, &[], output);
+    println!("Converted {} streamlines of {} to {}", streamlines.len(), input, output);
+}
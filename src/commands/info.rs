@@ -0,0 +1,347 @@
+//Print every field of the header of a volume or tractogram as text or JSON
+use getopts::Options;
+use wmparc::nifti::NIfTI1Header;
+use wmparc::{mgh, nifti, tck, trackvis, tractogram, trx, volume, vtk};
+
+use commands::json::Value;
+use commands::parse;
+
+type Affine = [[f32; 4]; 4];
+
+pub fn run(program: &str, args: &[String]) {
+    let brief = format!("Usage: {} info <file> [options]\n  file  Volume (.nii, .mgh, .mgz) or tractogram (.trk, .tck, .trx, .vtk, .vtp)", program);
+    let mut opts = Options::new();
+    opts.optflag("", "json", "print the header as JSON [optional]");
+    let matches = parse(&mut opts, args, &brief, 1);
+
+    let file_name = &matches.free[0];
+    let mut members = vec![("file", Value::from(file_name.as_str()))];
+    if volume::is_volume(file_name) {
+        members.extend(match volume::format(file_name) {
+            volume::Format::NIfTI1 => nifti_info(&nifti::read_header(file_name)),
+            volume::Format::Mgh => mgh_info(&mgh::read_header(file_name)),
+        });
+    } else {
+        members.extend(tractogram_info(file_name));
+    }
+
+    let info = Value::object(members);
+    if matches.opt_present("json") {
+        print!("{}", info.to_json());
+    } else if let Value::Object(ref members) = info {
+        print_text(members, 0);
+    }
+}
+
+//Print key: value lines, nested objects and matrices are indented below their key
+fn print_text(members: &[(String, Value)], level: usize) {
+    let indent = "  ".repeat(level);
+    for (key, value) in members.iter() {
+        match value {
+            Value::Object(nested) => {
+                println!("{}{}:", indent, key);
+                print_text(nested, level + 1);
+            },
+            Value::Array(rows) if rows.iter().any(|r| matches!(r, Value::Array(_))) => {
+                println!("{}{}:", indent, key);
+                for row in rows.iter() {
+                    println!("{}  {}", indent, text(row, true));
+                }
+            },
+            Value::Array(items) if items.iter().any(|i| matches!(i, Value::Object(_))) => {
+                println!("{}{}:", indent, key);
+                for item in items.iter() {
+                    if let Value::Object(nested) = item {
+                        let fields: Vec<String> = nested.iter().map(|(k, v)| format!("{}: {}", k, text(v, false))).collect();
+                        println!("{}  - {}", indent, fields.join(", "));
+                    }
+                }
+            },
+            _ => println!("{}{}: {}", indent, key, text(value, false)),
+        }
+    }
+}
+
+//Return the text of a value on one line, the numbers of matrix rows are aligned
+fn text(value: &Value, aligned: bool) -> String {
+    match value {
+        Value::Null => "-".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Int(i) => i.to_string(),
+        Value::Number(n) if aligned => format!("{:10.4}", n),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(|i| text(i, aligned)).collect::<Vec<String>>().join(if aligned { " " } else { ", " }),
+        Value::Object(_) => String::new(),
+    }
+}
+
+fn matrix(affine: &Affine) -> Value {
+    Value::Array(affine.iter().map(|row| Value::from(&row[..])).collect())
+}
+
+fn orientation(affine: &Affine) -> Value {
+    Value::from(String::from_utf8_lossy(&trackvis::orientation_codes(affine)).into_owned())
+}
+
+//Return a zero terminated text field as string
+fn name(bytes: &[u8]) -> Value {
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    Value::from(String::from_utf8_lossy(&bytes[..end]).trim_end().to_string())
+}
+
+fn datatype_name(datatype: i16) -> &'static str {
+    match datatype {
+        0 => "UNKNOWN",
+        1 => "BINARY",
+        2 => "UINT8",
+        4 => "INT16",
+        8 => "INT32",
+        16 => "FLOAT32",
+        32 => "COMPLEX64",
+        64 => "FLOAT64",
+        128 => "RGB24",
+        256 => "INT8",
+        512 => "UINT16",
+        768 => "UINT32",
+        1024 => "INT64",
+        1280 => "UINT64",
+        1536 => "FLOAT128",
+        1792 => "COMPLEX128",
+        2048 => "COMPLEX256",
+        2304 => "RGBA32",
+        _ => "INVALID",
+    }
+}
+
+fn xform_name(code: i16) -> &'static str {
+    match code {
+        0 => "unknown",
+        1 => "scanner_anat",
+        2 => "aligned_anat",
+        3 => "talairach",
+        4 => "mni_152",
+        5 => "template_other",
+        _ => "invalid",
+    }
+}
+
+fn slice_code_name(code: u8) -> &'static str {
+    match code {
+        0 => "unknown",
+        1 => "seq_inc",
+        2 => "seq_dec",
+        3 => "alt_inc",
+        4 => "alt_dec",
+        5 => "alt_inc2",
+        6 => "alt_dec2",
+        _ => "invalid",
+    }
+}
+
+fn space_units(units: u8) -> &'static str {
+    match units & 0x07 {
+        0 => "unknown",
+        1 => "m",
+        2 => "mm",
+        3 => "um",
+        _ => "invalid",
+    }
+}
+
+fn time_units(units: u8) -> &'static str {
+    match units & 0x38 {
+        0 => "unknown",
+        8 => "s",
+        16 => "ms",
+        24 => "us",
+        32 => "Hz",
+        40 => "ppm",
+        48 => "rad/s",
+        _ => "invalid",
+    }
+}
+
+fn nifti_info(header: &NIfTI1Header) -> Vec<(&'static str, Value)> {
+    let dim_info = Value::object(vec![
+        ("freq", Value::from(header.dim_info & 0x03)),
+        ("phase", Value::from((header.dim_info >> 2) & 0x03)),
+        ("slice", Value::from((header.dim_info >> 4) & 0x03)),
+    ]);
+
+    vec![
+        ("format", Value::from("NIfTI1")),
+        ("sizeof_hdr", Value::from(header.sizeof_hdr)),
+        ("data_type", name(&header.data_type)),
+        ("db_name", name(&header.db_name)),
+        ("extents", Value::from(header.extents)),
+        ("session_error", Value::from(header.session_error)),
+        ("regular", Value::from(header.regular)),
+        ("dim_info", dim_info),
+        ("dim", Value::from(&header.dim[..])),
+        ("intent_p1", Value::from(header.intent_p1)),
+        ("intent_p2", Value::from(header.intent_p2)),
+        ("intent_p3", Value::from(header.intent_p3)),
+        ("intent_code", Value::from(header.intent_code)),
+        ("intent_name", name(&header.intent_name)),
+        ("datatype", Value::from(header.datatype)),
+        ("datatype_name", Value::from(datatype_name(header.datatype))),
+        ("bitpix", Value::from(header.bitpix)),
+        ("slice_start", Value::from(header.slice_start)),
+        ("slice_end", Value::from(header.slice_end)),
+        ("slice_code", Value::from(header.slice_code)),
+        ("slice_code_name", Value::from(slice_code_name(header.slice_code))),
+        ("slice_duration", Value::from(header.slice_duration)),
+        ("pixdim", Value::from(&header.pixdim[..])),
+        ("xyzt_units", Value::from(header.xyzt_units)),
+        ("space_units", Value::from(space_units(header.xyzt_units))),
+        ("time_units", Value::from(time_units(header.xyzt_units))),
+        ("vox_offset", Value::from(header.vox_offset)),
+        ("scl_slope", Value::from(header.scl_slope)),
+        ("scl_inter", Value::from(header.scl_inter)),
+        ("cal_max", Value::from(header.cal_max)),
+        ("cal_min", Value::from(header.cal_min)),
+        ("toffset", Value::from(header.toffset)),
+        ("glmax", Value::from(header.glmax)),
+        ("glmin", Value::from(header.glmin)),
+        ("descrip", name(&header.descrip)),
+        ("aux_file", name(&header.aux_file)),
+        ("qform_code", Value::from(header.qform_code)),
+        ("qform_name", Value::from(xform_name(header.qform_code))),
+        ("sform_code", Value::from(header.sform_code)),
+        ("sform_name", Value::from(xform_name(header.sform_code))),
+        ("quatern_b", Value::from(header.quatern_b)),
+        ("quatern_c", Value::from(header.quatern_c)),
+        ("quatern_d", Value::from(header.quatern_d)),
+        ("qoffset_x", Value::from(header.qoffset_x)),
+        ("qoffset_y", Value::from(header.qoffset_y)),
+        ("qoffset_z", Value::from(header.qoffset_z)),
+        ("qform", matrix(&nifti::qform_affine(header))),
+        ("qform_orientation", orientation(&nifti::qform_affine(header))),
+        ("sform", matrix(&nifti::sform_affine(header))),
+        ("sform_orientation", orientation(&nifti::sform_affine(header))),
+        ("affine", matrix(&nifti::affine(header))),
+        ("orientation", orientation(&nifti::affine(header))),
+        ("magic", name(&header.magic)),
+    ]
+}
+
+fn mgh_info(header: &mgh::MghHeader) -> Vec<(&'static str, Value)> {
+    let data_type_name = match header.data_type {
+        0 => "UCHAR",
+        1 => "INT",
+        3 => "FLOAT",
+        4 => "SHORT",
+        _ => "INVALID",
+    };
+    let affine = mgh::affine(header);
+
+    vec![
+        ("format", Value::from("MGH")),
+        ("version", Value::from(header.version)),
+        ("dim", Value::from(&header.dim[..])),
+        ("data_type", Value::from(header.data_type)),
+        ("data_type_name", Value::from(data_type_name)),
+        ("dof", Value::from(header.dof)),
+        ("good_ras_flag", Value::from(header.good_ras_flag)),
+        ("spacing", Value::from(&header.spacing[..])),
+        ("mdc", Value::Array(header.mdc.iter().map(|c| Value::from(&c[..])).collect())),
+        ("c_ras", Value::from(&header.c_ras[..])),
+        ("affine", matrix(&affine)),
+        ("orientation", orientation(&affine)),
+    ]
+}
+
+fn data_arrays(arrays: &[tractogram::DataArray]) -> Value {
+    Value::Array(arrays.iter().map(|a| Value::object(vec![("name", Value::from(a.name.as_str())), ("dim", Value::from(a.dim))])).collect())
+}
+
+//Return the number of streamlines and points
+fn counts(streamlines: usize, points: usize) -> Vec<(&'static str, Value)> {
+    vec![
+        ("streamlines", Value::from(streamlines)),
+        ("points", Value::from(points)),
+    ]
+}
+
+fn tractogram_info(file_name: &str) -> Vec<(&'static str, Value)> {
+
+    match tractogram::format(file_name) {
+        tractogram::Format::TrackVis => {
+            let (header, tracks, complete) = trackvis::read_tracks_unchecked(file_name);
+            let n_scalars = (header.n_scalars as usize).min(10);
+            let n_properties = (header.n_properties as usize).min(10);
+            let mut info = vec![
+                ("format", Value::from("TrackVis")),
+                ("id_string", name(&header.id_string)),
+                ("dim", Value::from(&header.dim[..])),
+                ("voxel_size", Value::from(&header.voxel_size[..])),
+                ("origin", Value::from(&header.origin[..])),
+                ("n_scalars", Value::from(header.n_scalars)),
+                ("scalar_name", Value::Array(header.scalar_name[..n_scalars].iter().map(|n| name(n)).collect())),
+                ("n_properties", Value::from(header.n_properties)),
+                ("property_name", Value::Array(header.property_name[..n_properties].iter().map(|n| name(n)).collect())),
+                ("vox_to_ras", matrix(&header.vox_to_ras)),
+                ("vox_to_ras_orientation", if header.vox_to_ras[3][3] == 0.0 { Value::Null } else { orientation(&header.vox_to_ras) }),
+                ("voxel_order", name(&header.voxel_order)),
+                ("image_orientation_patient", Value::from(&header.image_orientation_patient[..])),
+                ("invert_x", Value::from(header.invert_x)),
+                ("invert_y", Value::from(header.invert_y)),
+                ("invert_z", Value::from(header.invert_z)),
+                ("swap_xy", Value::from(header.swap_xy)),
+                ("swap_yz", Value::from(header.swap_yz)),
+                ("swap_zx", Value::from(header.swap_zx)),
+                ("n_count", Value::from(header.n_count)),
+                ("version", Value::from(header.version)),
+                ("hdr_size", Value::from(header.hdr_size)),
+            ];
+            info.extend(counts(tracks.len(), tracks.iter().map(|t| t.points.len()).sum()));
+            //n_count 0 means the number of tracks was not stored
+            info.push(("n_count_matches", if header.n_count == 0 { Value::Null } else { Value::from(header.n_count as usize == tracks.len()) }));
+            info.push(("complete", Value::from(complete)));
+            info
+        },
+        tractogram::Format::Tck => {
+            let (header, streamlines, terminated) = tck::read_unchecked(file_name);
+            let properties = header.properties.iter().map(|(k, v)| (k.clone(), Value::from(v.as_str()))).collect();
+            let mut info = vec![
+                ("format", Value::from("MRtrix tck")),
+                ("properties", Value::Object(properties)),
+                ("datatype", Value::from(header.datatype.as_str())),
+                ("offset", Value::from(header.offset)),
+                ("count", Value::from(header.count)),
+            ];
+            info.extend(counts(streamlines.len(), streamlines.iter().map(|s| s.len()).sum()));
+            info.push(("count_matches", if header.count == 0 { Value::Null } else { Value::from(header.count == streamlines.len()) }));
+            info.push(("terminated", Value::from(terminated)));
+            info
+        },
+        tractogram::Format::Trx => {
+            let trx = trx::read(file_name);
+            let groups = trx.groups.iter()
+                .map(|g| Value::object(vec![("name", Value::from(g.name.as_str())), ("streamlines", Value::from(g.indices.len()))]))
+                .collect();
+            let mut info = vec![
+                ("format", Value::from("TRX")),
+                ("dimensions", Value::from(&trx.dimensions[..])),
+                ("voxel_to_rasmm", matrix(&trx.voxel_to_rasmm)),
+                ("orientation", orientation(&trx.voxel_to_rasmm)),
+                ("dpv", data_arrays(&trx.dpv)),
+                ("dps", data_arrays(&trx.dps)),
+                ("groups", Value::Array(groups)),
+            ];
+            info.extend(counts(trx.offsets.len(), trx.positions.len()));
+            info
+        },
+        tractogram::Format::Vtk => {
+            let poly = vtk::read(file_name);
+            let mut info = vec![
+                ("format", Value::from("VTK PolyData")),
+                ("point_data", data_arrays(&poly.point_data)),
+                ("cell_data", data_arrays(&poly.cell_data)),
+            ];
+            info.extend(counts(poly.lines.len(), poly.lines.iter().map(|l| l.len()).sum()));
+            info
+        },
+    }
+}
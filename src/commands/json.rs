@@ -0,0 +1,184 @@
+//Values of the machine-readable output (e.g. wmparc info --json) and their JSON text
+use std::fmt::Write;
+
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Number(f64),
+    String(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),   //Members in the order of the output.
+}
+
+impl Value {
+    //Return an object of key/value pairs
+    pub fn object(members: Vec<(&str, Value)>) -> Value {
+        Value::Object(members.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+    }
+
+    //Return the JSON text indented by two spaces per level
+    pub fn to_json(&self) -> String {
+        let mut text = String::new();
+        self.write(&mut text, 0);
+        text.push('\n');
+        text
+    }
+
+    //Return true for values that are written on one line
+    fn is_flat(&self) -> bool {
+        match self {
+            Value::Array(values) => values.iter().all(|v| !matches!(v, Value::Array(_) | Value::Object(_))),
+            Value::Object(members) => members.is_empty(),
+            _ => true,
+        }
+    }
+
+    fn write(&self, text: &mut String, level: usize) {
+        match self {
+            Value::Null => text.push_str("null"),
+            Value::Bool(b) => text.push_str(if *b { "true" } else { "false" }),
+            Value::Int(i) => text.push_str(&i.to_string()),
+            //JSON has no infinity and NaN
+            Value::Number(n) if !n.is_finite() => text.push_str("null"),
+            Value::Number(n) => text.push_str(&n.to_string()),
+            Value::String(s) => write_string(text, s),
+            Value::Array(values) if self.is_flat() => {
+                text.push('[');
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        text.push_str(", ");
+                    }
+                    value.write(text, level);
+                }
+                text.push(']');
+            },
+            Value::Array(values) => {
+                text.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    indent(text, level + 1);
+                    value.write(text, level + 1);
+                    text.push_str(if i + 1 < values.len() { ",\n" } else { "\n" });
+                }
+                indent(text, level);
+                text.push(']');
+            },
+            Value::Object(members) if members.is_empty() => text.push_str("{}"),
+            Value::Object(members) => {
+                text.push_str("{\n");
+                for (i, (key, value)) in members.iter().enumerate() {
+                    indent(text, level + 1);
+                    write_string(text, key);
+                    text.push_str(": ");
+                    value.write(text, level + 1);
+                    text.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                indent(text, level);
+                text.push('}');
+            },
+        }
+    }
+}
+
+fn indent(text: &mut String, level: usize) {
+    for _ in 0..level {
+        text.push_str("  ");
+    }
+}
+
+fn write_string(text: &mut String, s: &str) {
+    text.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => text.push_str("\\\""),
+            '\\' => text.push_str("\\\\"),
+            '\n' => text.push_str("\\n"),
+            '\r' => text.push_str("\\r"),
+            '\t' => text.push_str("\\t"),
+            c if (c as u32) < 0x20 => { let _ = write!(text, "\\u{:04x}", c as u32); },
+            c => text.push(c),
+        }
+    }
+    text.push('"');
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value { Value::Bool(b) }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Value { Value::String(s.to_string()) }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Value { Value::String(s) }
+}
+
+//The shortest text of the f32 is kept, e.g. 0.1 instead of 0.10000000149011612
+impl From<f32> for Value {
+    fn from(n: f32) -> Value { Value::Number(n.to_string().parse().unwrap_or(f64::NAN)) }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Value { Value::Number(n) }
+}
+
+macro_rules! from_int {
+    ($($t:ty),*) => { $(impl From<$t> for Value { fn from(i: $t) -> Value { Value::Int(i as i64) } })* };
+}
+from_int!(u8, i16, u16, i32, u32, i64, usize);
+
+impl<T: Clone + Into<Value>> From<&[T]> for Value {
+    fn from(values: &[T]) -> Value { Value::Array(values.iter().map(|v| v.clone().into()).collect()) }
+}
+
+impl<T: Into<Value>> From<Vec<T>> for Value {
+    fn from(values: Vec<T>) -> Value { Value::Array(values.into_iter().map(|v| v.into()).collect()) }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value { value.map_or(Value::Null, |v| v.into()) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nested_values_are_indented() {
+        let value = Value::object(vec![
+            ("format", Value::from("TRX")),
+            ("dim", Value::from(&[12i16, 10, 8][..])),
+            ("affine", Value::Array(vec![Value::from(&[-2.0f32, 0.0][..]), Value::from(&[0.0f32, 2.0][..])])),
+            ("groups", Value::Array(vec![Value::object(vec![("name", Value::from("cst")), ("streamlines", Value::from(2usize))])])),
+            ("empty", Value::object(Vec::new())),
+            ("count", Value::from(None::<usize>)),
+            ("valid", Value::from(true)),
+        ]);
+        assert_eq!(value.to_json(), concat!(
+            "{\n",
+            "  \"format\": \"TRX\",\n",
+            "  \"dim\": [12, 10, 8],\n",
+            "  \"affine\": [\n",
+            "    [-2, 0],\n",
+            "    [0, 2]\n",
+            "  ],\n",
+            "  \"groups\": [\n",
+            "    {\n",
+            "      \"name\": \"cst\",\n",
+            "      \"streamlines\": 2\n",
+            "    }\n",
+            "  ],\n",
+            "  \"empty\": {},\n",
+            "  \"count\": null,\n",
+            "  \"valid\": true\n",
+            "}\n"));
+    }
+
+    #[test]
+    fn numbers_and_strings_are_valid_json() {
+        let value = Value::from(vec![Value::from(0.1f32), Value::from(f32::NAN), Value::from(f64::INFINITY), Value::from(-3i32)]);
+        assert_eq!(value.to_json(), "[0.1, null, null, -3]\n");
+        assert_eq!(Value::from("a \"b\"\\\n\t\u{1}").to_json(), "\"a \\\"b\\\"\\\\\\n\\t\\u0001\"\n");
+    }
+}
@@ -0,0 +1,56 @@
+//Subcommands of the command line program, every command parses its own options
+use std::process::exit;
+use getopts::{Matches, Options};
+use wmparc::nifti;
+use wmparc::volume;
+
+pub mod convert;
+pub mod info;
+pub mod json;
+pub mod parcellate;
+pub mod report;
+pub mod stats;
+pub mod validate;
+
+pub fn print_usage(brief: &str, opts: &Options) {
+    print!("{}", opts.usage(brief));
+}
+
+//Parse the options of a command, print the usage on -h/--help or with a wrong number of arguments
+pub fn parse(opts: &mut Options, args: &[String], brief: &str, arguments: usize) -> Matches {
+    opts.optflag("h", "help", "print this help menu");
+    let matches = match opts.parse(args) {
+        Err(f) => {
+            println!("{}", f);
+            print_usage(brief, opts);
+            exit(1);
+        },
+        Ok(m) => m,
+    };
+    if matches.opt_present("h") || matches.free.len() != arguments {
+        print_usage(brief, opts);
+        exit(1);
+    }
+    matches
+}
+
+//Parse an optional non-negative number (e.g. a distance in mm), exit with a message on invalid values
+pub fn parse_number(value: Option<String>, name: &str, default: f32) -> f32 {
+    match value.map(|v| v.parse::<f32>()) {
+        None => default,
+        Some(Ok(v)) if v >= 0.0 => v,
+        Some(_) => {
+            println!("Invalid value for --{}, expected a non-negative number.", name);
+            exit(1);
+        },
+    }
+}
+
+//Read a volume (e.g. a mask or a scalar image like FA) that has to match the grid of the segmentation
+pub fn read_matching(file_name: &str, nheader: &nifti::NIfTI1Header, nifti_file: &str) -> nifti::NIfTI1Data {
+    let (mheader, mdata) = volume::read( file_name );
+    if mheader.dim[1..4] != nheader.dim[1..4] {
+        panic!("The image {} does not match the dimensions of {}!", file_name, nifti_file);
+    }
+    mdata
+}
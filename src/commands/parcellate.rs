@@ -0,0 +1,382 @@
+//Parcellation of the white matter by the cortex labels of the tracts
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::process::exit;
+use getopts::Options;
+use wmparc::filter::Filter;
+use wmparc::{lut, nifti, profile, stats, tractogram, volume};
+use wmparc::{CortexLabels, EndpointRule, Kernel, Parcellation, TieBreak};
+
+use commands::json::Value;
+use commands::report;
+use commands::report::Timer;
+use commands::{parse, parse_number, print_usage, read_matching};
+
+//Insert the label in front of the extension of a file name (tracts.trk -> tracts_1003.trk)
+fn split_file_name(file_name: &str, label: i32) -> String {
+    let path = Path::new(file_name);
+    let stem = path.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(e) => format!("{}_{}.{}", stem, label, e.to_string_lossy()),
+        None => format!("{}_{}", stem, label),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+//Return the name of a scalar image for the column names, the file name without extension (fa.nii -> fa)
+fn scalar_name(file_name: &str) -> String {
+    let name = Path::new(file_name).file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let lower = name.to_lowercase();
+    for extension in [".nii", ".mgh", ".mgz", ".mgh.gz"].iter() {
+        if lower.ends_with(extension) {
+            return name[..name.len() - extension.len()].to_string();
+        }
+    }
+    name
+}
+
+//Write the selected tracts together with their cortex label
+fn write_labelled_tracts(file_name: &str, nheader: &nifti::NIfTI1Header, source: &tractogram::Source,
+                         tract_labels: &[i32], selection: &[usize]) {
+    let labels = tractogram::DataArray {
+        name: "cortex_label".to_string(),
+        dim: 1,
+        data: selection.iter().map(|i| tract_labels[*i] as f32).collect(),
+    };
+    tractogram::write_selection(source, nheader, selection, &[labels], file_name);
+}
+
+pub fn run(program: &str, args: &[String]) {
+    /*
+     Beginning of parsing command line arguments/options
+    */
+    let brief = format!("Usage: {} parcellate <tract_file> [options]", program);
+    let mut opts = Options::new();
+    opts.optopt("n", "nifti", "path to the image that represents the cortex parcellation, e.g. aparc+aseg (.nii, .mgh, .mgz) [required]", "FILE");
+    opts.optopt("o", "output", "path to the output file (.nii, .mgh, .mgz) [optional]", "FILE");
+    opts.optopt("t", "tracts-output", "path to write the tracts with their cortex label 'cortex_label' as track property (.trk), dps array (.trx) or cell data (.vtk, .vtp) [optional]", "FILE");
+    opts.optflag("s", "split", "split the tracts output into one file per cortex label (e.g. tracts_1003.trk) [optional]");
+    opts.optopt("l", "lut", "path to write a FreeSurfer colour lookup table of the output labels [optional]", "FILE");
+    opts.optopt("", "itksnap", "path to write an ITK-SNAP label description file of the output labels [optional]", "FILE");
+    opts.optflag("w", "wm-offset", "renumber the white matter labels by 2000 like FreeSurfer (wm-lh-*, wm-rh-*, 1001 -> 3001) [optional]");
+    opts.optflag("m", "merge", "write the white matter labels into a copy of the input image, keeping all other structures, implies --wm-offset [optional]");
+    opts.optflag("", "labelwm", "label the white matter like mri_aparc2aseg --labelwm: keep the input labels, renumber into the 3000/4000 range and label the remaining white matter 5001/5002 [optional]");
+    opts.optflag("", "hemisphere", "reject votes of cortex labels from the other hemisphere, the hemisphere of a voxel is given by its white matter label [optional]");
+    opts.optopt("", "hemisphere-mask", "path to an image with the hemisphere of every voxel (1 left, 2 right), implies --hemisphere [optional]", "FILE");
+    opts.optopt("", "depth-min", "only label white matter at least this far from the cortex (default 0) [optional]", "MM");
+    opts.optopt("", "depth-max", "only label white matter at most this far from the cortex, 5 for the superficial white matter of FreeSurfer [optional]", "MM");
+    opts.optopt("", "depth-map", "path to write the distance of every white matter voxel to the cortex in mm (.nii, .mgh, .mgz) [optional]", "FILE");
+    opts.optopt("", "min-length", "only use tracts at least this long in mm [optional]", "MM");
+    opts.optopt("", "max-length", "only use tracts at most this long in mm [optional]", "MM");
+    opts.optopt("", "max-angle", "only use tracts that turn by at most this angle in degrees [optional]", "DEG");
+    opts.optflag("", "endpoints", "only use tracts with both endpoints in the cortex or deep grey matter [optional]");
+    opts.optflag("", "midline", "reject tracts that cross the midline outside the corpus callosum [optional]");
+    opts.optmulti("", "include", "only use tracts that pass through this mask, can be given several times [optional]", "FILE");
+    opts.optmulti("", "exclude", "reject tracts that pass through this mask, can be given several times [optional]", "FILE");
+    opts.optopt("", "weights", "path to a text file with a weight per tract, e.g. of tcksift2 [optional]", "FILE");
+    opts.optopt("", "weights-property", "name of the track property (.trk), dps array (.trx) or cell data (.vtk, .vtp) with a weight per tract [optional]", "NAME");
+    opts.optopt("j", "threads", "number of threads, the result does not depend on it (default: number of cores) [optional]", "N");
+    opts.optopt("", "tie-break", "rule for labels with the same probability: lowest (label), votes (most direct votes) or endpoint (nearest cortex endpoint) (default: lowest) [optional]", "RULE");
+    opts.optopt("", "kernel", "neighbourhood whose votes are added to the votes of a voxel: none, octant (7 neighbours) or full (26 neighbours) (default: octant) [optional]", "KERNEL");
+    opts.optopt("", "endpoint", "cortex voxel along a tract that gives its label: first or last (default: last) [optional]", "RULE");
+    opts.optopt("", "label-stats", "path to write a table (tab separated) with the white matter volume, streamlines, mean length and scalar means of every cortex label [optional]", "FILE");
+    opts.optopt("", "scalar-stats", "path to write a table (tab separated) with mean, median, standard deviation and percentiles of every scalar image in every white matter parcel [optional]", "FILE");
+    opts.optopt("", "profiles", "path to write the mean along-tract profile (comma separated) of every scalar image per cortex label or pair of labels [optional]", "FILE");
+    opts.optopt("", "profile-points", "number of equidistant points every streamline is resampled to for the profiles (default: 100) [optional]", "N");
+    opts.optopt("", "profile-by", "groups of streamlines averaged for the profiles: label (cortex label of the streamline) or pair (first and last cortex label) (default: label) [optional]", "GROUP");
+    opts.optmulti("", "scalar", "path to a scalar image in the grid of the segmentation (e.g. FA) for the statistics and profiles, can be given several times [optional]", "FILE");
+    opts.optopt("", "report", "path to write a JSON report with the counts, label volumes, timing and input file hashes of the run [optional]", "FILE");
+    let matches = parse(&mut opts, args, &brief, 1);
+
+    //Parse options
+    let nifti_file: String = match matches.opt_str("n"){
+        None => {
+            print_usage(&brief, &opts);
+            exit(1);
+        },
+        Some(s) => s,
+    };
+
+    let output_file: String = matches.opt_str("o").unwrap_or_default();
+    //Fail early on unknown output formats
+    if !output_file.is_empty() {
+        volume::format(&output_file);
+    }
+
+    let tracts_output_file: String = matches.opt_str("t").unwrap_or_default();
+    let split = matches.opt_present("s");
+    if !tracts_output_file.is_empty() && !split &&
+        !tractogram::has_properties(tractogram::output_format(&tracts_output_file)) {
+        println!("The cortex labels can not be stored in {}, use --split to write one file per label.", tracts_output_file);
+        exit(1);
+    }
+
+    let lut_file: String = matches.opt_str("l").unwrap_or_default();
+    let itksnap_file: String = matches.opt_str("itksnap").unwrap_or_default();
+    let labelwm = matches.opt_present("labelwm");
+    let merge = matches.opt_present("m") || labelwm;
+    let wm_offset = matches.opt_present("w") || merge;
+
+    let hemisphere_mask_file: String = matches.opt_str("hemisphere-mask").unwrap_or_default();
+    let hemisphere = matches.opt_present("hemisphere") || !hemisphere_mask_file.is_empty();
+
+    let depth_min = parse_number(matches.opt_str("depth-min"), "depth-min", 0.0);
+    let depth_max = parse_number(matches.opt_str("depth-max"), "depth-max", f32::INFINITY);
+    if depth_min > depth_max {
+        println!("Invalid depth band, --depth-min {} is larger than --depth-max {}.", depth_min, depth_max);
+        exit(1);
+    }
+    let depth_map_file: String = matches.opt_str("depth-map").unwrap_or_default();
+    if !depth_map_file.is_empty() {
+        volume::format(&depth_map_file);
+    }
+
+    let mut filter = Filter {
+        min_length: parse_number(matches.opt_str("min-length"), "min-length", 0.0),
+        max_length: parse_number(matches.opt_str("max-length"), "max-length", f32::INFINITY),
+        max_angle: parse_number(matches.opt_str("max-angle"), "max-angle", 180.0),
+        endpoints: matches.opt_present("endpoints"),
+        midline: matches.opt_present("midline"),
+        ..Default::default()
+    };
+    let include_files = matches.opt_strs("include");
+    let exclude_files = matches.opt_strs("exclude");
+
+    let weights_file: String = matches.opt_str("weights").unwrap_or_default();
+    let weights_property: String = matches.opt_str("weights-property").unwrap_or_default();
+    if !weights_file.is_empty() && !weights_property.is_empty() {
+        println!("Only one of --weights and --weights-property can be given.");
+        exit(1);
+    }
+
+    let threads: Option<usize> = match matches.opt_str("j").map(|t| t.parse::<usize>()) {
+        None => None,
+        Some(Ok(t)) if t > 0 => Some(t),
+        Some(_) => {
+            println!("Invalid value for --threads, expected a positive number.");
+            exit(1);
+        },
+    };
+
+    let tie_break = match matches.opt_str("tie-break").as_deref() {
+        None | Some("lowest") => TieBreak::Lowest,
+        Some("votes") => TieBreak::Votes,
+        Some("endpoint") => TieBreak::Endpoint,
+        Some(rule) => {
+            println!("Unknown tie break rule {}, expected lowest, votes or endpoint.", rule);
+            exit(1);
+        },
+    };
+
+    let kernel = match matches.opt_str("kernel").as_deref() {
+        None | Some("octant") => Kernel::Octant,
+        Some("none") => Kernel::None,
+        Some("full") => Kernel::Full,
+        Some(kernel) => {
+            println!("Unknown kernel {}, expected none, octant or full.", kernel);
+            exit(1);
+        },
+    };
+
+    let endpoint_rule = match matches.opt_str("endpoint").as_deref() {
+        None | Some("last") => EndpointRule::Last,
+        Some("first") => EndpointRule::First,
+        Some(rule) => {
+            println!("Unknown endpoint rule {}, expected first or last.", rule);
+            exit(1);
+        },
+    };
+
+    let report_file: String = matches.opt_str("report").unwrap_or_default();
+    let label_stats_file: String = matches.opt_str("label-stats").unwrap_or_default();
+    let scalar_stats_file: String = matches.opt_str("scalar-stats").unwrap_or_default();
+    let profiles_file: String = matches.opt_str("profiles").unwrap_or_default();
+    let scalar_files = matches.opt_strs("scalar");
+    if !scalar_files.is_empty() && label_stats_file.is_empty() && scalar_stats_file.is_empty() && profiles_file.is_empty() {
+        println!("The scalar images are only used for the statistics and profiles, use --label-stats, --scalar-stats or --profiles to write them.");
+        exit(1);
+    }
+    if (!scalar_stats_file.is_empty() || !profiles_file.is_empty()) && scalar_files.is_empty() {
+        println!("The scalar statistics and profiles need at least one scalar image (--scalar).");
+        exit(1);
+    }
+
+    let profile_points: usize = match matches.opt_str("profile-points").map(|p| p.parse::<usize>()) {
+        None => 100,
+        Some(Ok(p)) if p > 1 => p,
+        Some(_) => {
+            println!("Invalid value for --profile-points, expected a number larger than 1.");
+            exit(1);
+        },
+    };
+
+    let profile_grouping = match matches.opt_str("profile-by").as_deref() {
+        None | Some("label") => profile::Grouping::Label,
+        Some("pair") => profile::Grouping::Pair,
+        Some(group) => {
+            println!("Unknown profile grouping {}, expected label or pair.", group);
+            exit(1);
+        },
+    };
+
+    //Parse argument
+    let track_file = matches.free[0].clone();
+
+    println!("Tractogram Input: {}, Parcellation Input: {}, Output: {}",
+             track_file, nifti_file, output_file);
+
+    /*
+    End of parsing command line arguments/options
+    */
+
+    //Read the mandantory data
+    let mut timer = Timer::new();
+    let (nheader, ndata) = volume::read( &nifti_file );
+    let hemisphere_mask = if hemisphere_mask_file.is_empty() {
+        None
+    } else {
+        Some(read_matching(&hemisphere_mask_file, &nheader, &nifti_file))
+    };
+    filter.include = include_files.iter().map(|f| read_matching(f, &nheader, &nifti_file)).collect();
+    filter.exclude = exclude_files.iter().map(|f| read_matching(f, &nheader, &nifti_file)).collect();
+    let scalars: Vec<nifti::NIfTI1Data> = scalar_files.iter().map(|f| read_matching(f, &nheader, &nifti_file)).collect();
+    timer.stage("read_image");
+
+    //Keep the stored tracts, so they can be written with the labels without loss
+    let (source, tracts) = tractogram::read_source( &track_file, &nheader );
+
+    //Every tract votes with its weight, by default all tracts are weighted equally
+    let weights = if !weights_file.is_empty() {
+        Some(tractogram::read_values(&weights_file))
+    } else if !weights_property.is_empty() {
+        match tractogram::streamline_values(&source, &weights_property) {
+            Some(w) => Some(w),
+            None => panic!("Could not find the weights {} in {}!", weights_property, track_file),
+        }
+    } else {
+        None
+    };
+    timer.stage("read_tracts");
+
+    let cortex_labels = CortexLabels::default();
+    let mut parcellation = Parcellation::new()
+        .cortex_labels(cortex_labels.clone())
+        .kernel(kernel)
+        .endpoint_rule(endpoint_rule)
+        .tie_break(tie_break)
+        .hemisphere(hemisphere)
+        .depth_band(depth_min, depth_max)
+        .depth_map(!depth_map_file.is_empty())
+        .wm_offset(wm_offset)
+        .merge(merge)
+        .labelwm(labelwm)
+        .filter(filter);
+    if let Some(threads) = threads {
+        parcellation = parcellation.threads(threads);
+    }
+    if let Some(mask) = hemisphere_mask {
+        parcellation = parcellation.hemisphere_mask(mask);
+    }
+
+    let result = parcellation.run(&nheader, &ndata, &tracts, weights.as_deref());
+    timer.stage("parcellation");
+    let tract_labels = &result.tract_labels;
+    let keep = &result.keep;
+
+    if let Some(ref counts) = result.stats.filter {
+        println!("Filter: {} of {} tracts kept", counts.kept, counts.total);
+        for (name, count) in counts.rejected.iter().filter(|c| c.1 > 0) {
+            println!("  {}: {} tracts rejected", name, count);
+        }
+    }
+    if hemisphere {
+        println!("Hemisphere constraint: {} of {} votes rejected", result.stats.rejected_votes, result.stats.votes);
+    }
+
+    //Statistics per cortex label
+    let scalar_names: Vec<String> = scalar_files.iter().map(|f| scalar_name(f)).collect();
+    if !label_stats_file.is_empty() {
+        println!("Write label statistics");
+        stats::write_label_stats(&stats::label_stats(&nheader, &result, &tracts, &scalars), &scalar_names, &label_stats_file);
+    }
+    if !scalar_stats_file.is_empty() {
+        println!("Write scalar statistics");
+        stats::write_scalar_stats(&stats::scalar_stats(&result, &scalars), &scalar_names, &scalar_stats_file);
+    }
+
+    //Along-tract profiles of the labelled streamlines
+    if !profiles_file.is_empty() {
+        println!("Write along-tract profiles");
+        let profiles = profile::profiles(&nheader, &ndata, &result, &tracts, &scalars, profile_points, profile_grouping,
+                                         &cortex_labels, endpoint_rule);
+        profile::write_profiles(&profiles, &scalar_names, profile_grouping, &profiles_file);
+    }
+
+    //Voxels per label of the output, counted before the volume is written
+    let label_voxels = if report_file.is_empty() { BTreeMap::new() } else { stats::label_voxels(&result.volume) };
+
+    //Write the lookup tables of the parcellation
+    if !lut_file.is_empty() || !itksnap_file.is_empty() {
+        let labels = lut::labels(&result.volume);
+        if !lut_file.is_empty() {
+            println!("Write lookup table");
+            lut::write_freesurfer(&labels, &lut_file);
+        }
+        if !itksnap_file.is_empty() {
+            println!("Write ITK-SNAP label description");
+            lut::write_itksnap(&labels, &itksnap_file);
+        }
+    }
+
+    //Write the output
+    if !output_file.is_empty() {
+        println!("Write output");
+        volume::write(nheader, result.volume, &output_file);
+    }
+
+    //Write the depth of the white matter
+    if let Some(depth) = result.depth {
+        println!("Write depth map");
+        volume::write(nheader, depth, &depth_map_file);
+    }
+
+    //Write the tracts together with their cortex label
+    if !tracts_output_file.is_empty() {
+        println!("Write labelled tracts");
+
+        if split {
+            //One file per cortex label, tracts without a label are not written
+            let mut labels: Vec<i32> = tract_labels.iter().cloned().filter(|l| *l > 0).collect();
+            labels.sort();
+            labels.dedup();
+
+            for label in labels.iter() {
+                let selection: Vec<usize> = (0..tracts.len()).filter(|i| keep[*i] && tract_labels[*i] == *label).collect();
+                let file_name = split_file_name(&tracts_output_file, *label);
+                write_labelled_tracts(&file_name, &nheader, &source, tract_labels, &selection);
+            }
+        } else {
+            let selection: Vec<usize> = (0..tracts.len()).filter(|i| keep[*i]).collect();
+            write_labelled_tracts(&tracts_output_file, &nheader, &source, tract_labels, &selection);
+        }
+    }
+
+    timer.stage("write");
+
+    report::print_summary(&result.stats, depth_min > 0.0 || depth_max.is_finite(), &timer);
+    if !report_file.is_empty() {
+        let mut inputs = vec![("tracts", report::input(&track_file)), ("nifti", report::input(&nifti_file))];
+        if !weights_file.is_empty() {
+            inputs.push(("weights", report::input(&weights_file)));
+        }
+        if !hemisphere_mask_file.is_empty() {
+            inputs.push(("hemisphere_mask", report::input(&hemisphere_mask_file)));
+        }
+        inputs.push(("include", Value::Array(include_files.iter().map(|f| report::input(f)).collect())));
+        inputs.push(("exclude", Value::Array(exclude_files.iter().map(|f| report::input(f)).collect())));
+        inputs.push(("scalars", Value::Array(scalar_files.iter().map(|f| report::input(f)).collect())));
+
+        println!("Write report");
+        report::write(&report::report(&result.stats, &label_voxels, &nheader, args, inputs, &timer), &report_file);
+    }
+}
@@ -0,0 +1,144 @@
+//Summary of a parcellation run for quality control, printed at the end and written as JSON with --report
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+use std::time::Instant;
+
+use sha2::{Digest, Sha256};
+use wmparc::nifti::NIfTI1Header;
+use wmparc::{lut, nifti, Stats};
+
+use commands::json::Value;
+
+//Duration of the stages of a run
+pub struct Timer {
+    start: Instant,
+    last: Instant,
+    pub stages: Vec<(&'static str, f64)>,
+}
+
+impl Timer {
+    pub fn new() -> Timer {
+        let now = Instant::now();
+        Timer { start: now, last: now, stages: Vec::new() }
+    }
+
+    //End the current stage
+    pub fn stage(&mut self, name: &'static str) {
+        let now = Instant::now();
+        self.stages.push((name, now.duration_since(self.last).as_secs_f64()));
+        self.last = now;
+    }
+
+    pub fn total(&self) -> f64 {
+        self.last.duration_since(self.start).as_secs_f64()
+    }
+}
+
+//Return the SHA-256 hash of a file as hex string, None for directories (e.g. TRX directories)
+pub fn sha256(file_name: &str) -> Option<String> {
+    let path = Path::new(file_name);
+    if path.is_dir() {
+        return None;
+    }
+    let mut file = match File::open(path) {
+        Err(why) => panic!("Could not open {}: {}", path.display(), why),
+        Ok(file) => file,
+    };
+
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 1 << 16];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => hasher.update(&buffer[..n]),
+            Err(why) => panic!("Could not read data of {}: {}", path.display(), why),
+        }
+    }
+    Some(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+//Return the file name together with its hash
+pub fn input(file_name: &str) -> Value {
+    Value::object(vec![("file", Value::from(file_name)), ("sha256", Value::from(sha256(file_name)))])
+}
+
+//Print the summary of a run
+pub fn print_summary(stats: &Stats, depth_band: bool, timer: &Timer) {
+    println!("Summary:");
+    println!("  Streamlines read: {}", stats.tracts);
+    if stats.filter.is_some() {
+        println!("  Streamlines kept by the filters: {}", stats.kept_tracts);
+    }
+    println!("  Streamlines without cortex label: {}", stats.kept_tracts - stats.labelled_tracts);
+    println!("  Voxels with votes: {}", stats.voted_voxels);
+    println!("  Voxels skipped as not white matter: {}", stats.non_wm_voxels);
+    if depth_band {
+        println!("  Voxels skipped outside the depth band: {}", stats.depth_voxels);
+    }
+    println!("  Voxels labelled: {}", stats.labelled_voxels);
+    let stages: Vec<String> = timer.stages.iter().map(|(name, time)| format!("{} {:.2} s", name.replace('_', " "), time)).collect();
+    println!("  Time: {} (total {:.2} s)", stages.join(", "), timer.total());
+}
+
+//Return the report of a run, label_voxels are the voxel counts of the labels in the output volume
+pub fn report(stats: &Stats, label_voxels: &BTreeMap<i32, usize>, nheader: &NIfTI1Header, arguments: &[String],
+              inputs: Vec<(&str, Value)>, timer: &Timer) -> Value {
+    let voxel_volume = nifti::voxel_volume(nheader);
+
+    let rejected = match stats.filter {
+        Some(ref counts) => Value::Object(counts.rejected.iter().map(|(name, count)| (name.to_string(), Value::from(*count))).collect()),
+        None => Value::Null,
+    };
+    let labels = label_voxels.iter().map(|(&label, &voxels)| {
+        Value::object(vec![
+            ("label", Value::from(label)),
+            ("name", Value::from(lut::lookup(label).0)),
+            ("voxels", Value::from(voxels)),
+            ("volume_mm3", Value::from(voxels as f64 * voxel_volume as f64)),
+        ])
+    }).collect();
+
+    let mut timing: Vec<(&str, Value)> = timer.stages.iter().map(|(name, time)| (*name, Value::from(*time))).collect();
+    timing.push(("total", Value::from(timer.total())));
+
+    Value::object(vec![
+        ("version", Value::from(env!("CARGO_PKG_VERSION"))),
+        ("arguments", Value::from(arguments.to_vec())),
+        ("inputs", Value::object(inputs)),
+        ("streamlines", Value::object(vec![
+            ("read", Value::from(stats.tracts)),
+            ("kept", Value::from(stats.kept_tracts)),
+            ("labelled", Value::from(stats.labelled_tracts)),
+            ("unlabelled", Value::from(stats.kept_tracts - stats.labelled_tracts)),
+            ("filter_rejected", rejected),
+        ])),
+        ("votes", Value::object(vec![
+            ("total", Value::from(stats.votes)),
+            ("rejected_hemisphere", Value::from(stats.rejected_votes)),
+        ])),
+        ("voxels", Value::object(vec![
+            ("voted", Value::from(stats.voted_voxels)),
+            ("not_white_matter", Value::from(stats.non_wm_voxels)),
+            ("outside_depth_band", Value::from(stats.depth_voxels)),
+            ("labelled", Value::from(stats.labelled_voxels)),
+        ])),
+        ("voxel_volume_mm3", Value::from(voxel_volume)),
+        ("labels", Value::Array(labels)),
+        ("timing_s", Value::object(timing)),
+    ])
+}
+
+//Write a JSON report
+pub fn write(report: &Value, file_name: &str) {
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path) {
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+    if let Err(why) = file.write_all(report.to_json().as_bytes()) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
@@ -0,0 +1,36 @@
+//Voxel counts and volumes of the labels of a volume
+use std::fs::File;
+use std::io::prelude::*;
+use getopts::Options;
+use wmparc::{lut, nifti, stats, volume};
+
+use commands::parse;
+
+pub fn run(program: &str, args: &[String]) {
+    let brief = format!("Usage: {} stats <volume> [options]\n  Print the number of voxels and the volume in mm^3 of every label (tab separated).", program);
+    let mut opts = Options::new();
+    opts.optopt("o", "output", "path to write the table to instead of the standard output [optional]", "FILE");
+    let matches = parse(&mut opts, args, &brief, 1);
+
+    let (header, data) = volume::read(&matches.free[0]);
+    let voxel_volume = nifti::voxel_volume(&header);
+
+    let mut text = String::from("label\tname\tvoxels\tvolume_mm3\n");
+    for (label, voxels) in stats::label_voxels(&data) {
+        let (name, _) = lut::lookup(label);
+        text.push_str(&format!("{}\t{}\t{}\t{:.2}\n", label, name, voxels, voxels as f32 * voxel_volume));
+    }
+
+    match matches.opt_str("o") {
+        None => print!("{}", text),
+        Some(file_name) => {
+            let mut file = match File::create(&file_name) {
+                Err(why) => panic!("Could not create {}: {}", file_name, why),
+                Ok(file) => file,
+            };
+            if let Err(why) = file.write_all(text.as_bytes()) {
+                panic!("Could not write data of {}: {}", file_name, why);
+            }
+        },
+    }
+}
@@ -0,0 +1,114 @@
+//Check that a tractogram and a label image fit together before the parcellation
+use std::process::exit;
+use getopts::Options;
+use wmparc::trackvis::{Fiber, Position};
+use wmparc::{labels, nifti, trackvis, tractogram, volume, CortexLabels};
+
+use commands::{parse, print_usage};
+
+//Relative difference up to which voxel sizes and affines are considered equal
+const TOLERANCE: f32 = 1e-3;
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+pub fn run(program: &str, args: &[String]) {
+    let brief = format!("Usage: {} validate <tract_file> [options]\n  Check that the tracts and the cortex parcellation can be used together, \
+                         exits with 1 if they can not.", program);
+    let mut opts = Options::new();
+    opts.optopt("n", "nifti", "path to the image that represents the cortex parcellation, e.g. aparc+aseg [required]", "FILE");
+    let matches = parse(&mut opts, args, &brief, 1);
+
+    let nifti_file = match matches.opt_str("n") {
+        Some(s) => s,
+        None => {
+            print_usage(&brief, &opts);
+            exit(1);
+        },
+    };
+    let track_file = &matches.free[0];
+
+    let (nheader, ndata) = volume::read(&nifti_file);
+    let cortex_labels = CortexLabels::default();
+    let (source, streamlines) = tractogram::read_source(track_file, &nheader);
+    let fibers: Vec<Fiber> = streamlines.iter().map(|s| trackvis::fiber(s)).collect();
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    //Labels of the parcellation
+    let values: Vec<f32> = ndata[0].iter().flatten().flatten().cloned().collect();
+    if values.iter().any(|v| cortex_labels.contains(*v)) {
+        println!("OK: {} contains cortex labels", nifti_file);
+    } else {
+        println!("Error: {} contains no cortex labels (1001-1035, 2001-2035)", nifti_file);
+        errors += 1;
+    }
+    if values.iter().any(|v| labels::is_white_matter(*v)) {
+        println!("OK: {} contains white matter labels", nifti_file);
+    } else {
+        println!("Error: {} contains no white matter labels (2, 41, 251-255)", nifti_file);
+        errors += 1;
+    }
+
+    //TrackVis tracts are used in the voxel grid of their header, which has to be the grid of the image
+    if let tractogram::Source::TrackVis(ref header, _) = source {
+        if header.dim[..] == nheader.dim[1..4] {
+            println!("OK: The dimensions of the TrackVis header match the image");
+        } else {
+            println!("Error: The dimensions of the TrackVis header {:?} do not match the image {:?}", header.dim, &nheader.dim[1..4]);
+            errors += 1;
+        }
+        if (0..3).all(|i| close(header.voxel_size[i], nheader.pixdim[i + 1].abs())) {
+            println!("OK: The voxel size of the TrackVis header matches the image");
+        } else {
+            println!("Error: The voxel size of the TrackVis header {:?} does not match the image {:?}", header.voxel_size, &nheader.pixdim[1..4]);
+            errors += 1;
+        }
+        let affine = nifti::affine(&nheader);
+        if header.vox_to_ras[3][3] == 0.0 {
+            println!("Warning: The TrackVis header has no voxel to RAS transformation, the tracts are assumed to be in the space of the image");
+            warnings += 1;
+        } else if (0..3).all(|r| (0..4).all(|c| close(header.vox_to_ras[r][c], affine[r][c]))) {
+            println!("OK: The voxel to RAS transformation of the TrackVis header matches the image");
+        } else {
+            println!("Warning: The voxel to RAS transformation of the TrackVis header differs from the image");
+            warnings += 1;
+        }
+    }
+
+    //Streamlines
+    if fibers.is_empty() {
+        println!("Error: {} contains no streamlines", track_file);
+        errors += 1;
+    } else {
+        let dim = [nheader.dim[1] as i32, nheader.dim[2] as i32, nheader.dim[3] as i32];
+        let inside = |p: &Position| p.x >= 0 && p.y >= 0 && p.z >= 0 && p.x < dim[0] && p.y < dim[1] && p.z < dim[2];
+        let label = |p: &Position| if inside(p) { ndata[0][p.z as usize][p.y as usize][p.x as usize] } else { 0.0 };
+
+        let outside = fibers.iter().filter(|f| !f.iter().all(inside)).count();
+        let cortex = fibers.iter().filter(|f| f.iter().any(|p| cortex_labels.contains(label(p)))).count();
+
+        if outside == 0 {
+            println!("OK: All {} streamlines lie inside the image", fibers.len());
+        } else if fibers.iter().all(|f| !f.iter().any(inside)) {
+            println!("Error: All {} streamlines lie outside the image", fibers.len());
+            errors += 1;
+        } else {
+            println!("Warning: {} of {} streamlines leave the image", outside, fibers.len());
+            warnings += 1;
+        }
+        if cortex == 0 {
+            println!("Error: No streamline reaches the cortex");
+            errors += 1;
+        } else {
+            println!("OK: {} of {} streamlines reach the cortex", cortex, fibers.len());
+        }
+    }
+
+    if errors > 0 {
+        println!("{} and {} can not be used together: {} errors, {} warnings", track_file, nifti_file, errors, warnings);
+        exit(1);
+    }
+    println!("{} and {} can be used together: {} warnings", track_file, nifti_file, warnings);
+}
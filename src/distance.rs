@@ -0,0 +1,91 @@
+//Euclidean distance transform of a volume in mm
+//Exact separable algorithm of Felzenszwalb and Huttenlocher (Distance Transforms of Sampled Functions, 2012),
+//the voxel sizes are taken into account by the position of the samples along every axis.
+use nifti::NIfTI1Data;
+
+//Squared distance of voxels without a feature voxel in reach
+const FAR: f64 = 1e20;
+
+//Squared distance transform of one line of samples with the given spacing (lower envelope of parabolas)
+fn transform_line(f: &[f64], spacing: f64) -> Vec<f64> {
+    let n = f.len();
+    let pos = |q: usize| q as f64 * spacing;
+
+    //Intersection of the parabolas rooted at q and p
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + pos(q) * pos(q)) - (f[p] + pos(p) * pos(p))) / (2.0 * (pos(q) - pos(p)))
+    };
+
+    //Roots of the parabolas in the lower envelope and the boundaries between them
+    let mut v = vec![0usize; n];
+    let mut z = vec![0.0f64; n + 1];
+    let mut k = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+
+    for q in 1..n {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+
+    //Fill in the values of the lower envelope
+    let mut d = vec![0.0f64; n];
+    k = 0;
+    for (q, value) in d.iter_mut().enumerate() {
+        while z[k + 1] < pos(q) {
+            k += 1;
+        }
+        let diff = pos(q) - pos(v[k]);
+        *value = diff * diff + f[v[k]];
+    }
+    d
+}
+
+//Return the distance in mm of every voxel of the first frame to the nearest feature voxel.
+//Feature voxels have the distance 0, all voxels are infinitely far away if there is no feature voxel.
+pub fn distance_map<F: Fn(f32) -> bool>(data: &NIfTI1Data, spacing: [f32; 3], feature: F) -> NIfTI1Data {
+    let nz = data[0].len();
+    let ny = if nz > 0 { data[0][0].len() } else { 0 };
+    let nx = if ny > 0 { data[0][0][0].len() } else { 0 };
+
+    //Squared distances in a flat buffer, x runs fastest
+    let mut buffer: Vec<f64> = data[0].iter().flatten().flatten()
+        .map(|v| if feature(*v) { 0.0 } else { FAR })
+        .collect();
+
+    //The transformation along every axis in turn gives the squared euclidean distance
+    let dims = [nx, ny, nz];
+    let strides = [1, nx, nx * ny];
+    for axis in 0..3 {
+        let (n, stride) = (dims[axis], strides[axis]);
+        let spacing = spacing[axis].abs() as f64;
+        for start in 0..buffer.len() {
+            //Every line starts at the first voxel along the axis
+            if (start / stride) % n != 0 {
+                continue;
+            }
+            let line: Vec<f64> = (0..n).map(|i| buffer[start + i * stride]).collect();
+            for (i, d) in transform_line(&line, spacing).into_iter().enumerate() {
+                buffer[start + i * stride] = d;
+            }
+        }
+    }
+
+    let mut values = buffer.into_iter().map(|d| if d >= FAR { f32::INFINITY } else { d.sqrt() as f32 });
+    let mut volume = Vec::with_capacity(nz);
+    for _ in 0..nz {
+        let mut slice = Vec::with_capacity(ny);
+        for _ in 0..ny {
+            slice.push(values.by_ref().take(nx).collect::<Vec<f32>>());
+        }
+        volume.push(slice);
+    }
+    vec![volume]
+}
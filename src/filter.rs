@@ -0,0 +1,189 @@
+//Filtering of the streamlines before the labelling
+//Every streamline is checked by the filters in the order below and counted for the first filter that rejects it.
+use labels;
+use labels::CortexLabels;
+use nifti::{NIfTI1Data, NIfTI1Header};
+use trackvis;
+use trackvis::{Position, Streamline};
+
+pub struct Filter {
+    pub min_length: f32,            //Minimum length in mm.
+    pub max_length: f32,            //Maximum length in mm.
+    pub max_angle: f32,             //Maximum turning angle in degrees.
+    pub endpoints: bool,            //Both endpoints have to be in the cortex or deep grey matter.
+    pub midline: bool,              //Reject streamlines crossing the midline outside the corpus callosum.
+    pub include: Vec<NIfTI1Data>,   //Masks every streamline has to pass through.
+    pub exclude: Vec<NIfTI1Data>,   //Masks no streamline may pass through.
+}
+
+//Number of rejected streamlines per filter
+pub struct FilterCounts {
+    pub total: usize,
+    pub kept: usize,
+    pub rejected: Vec<(&'static str, usize)>,
+}
+
+//Filter that keeps every streamline
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter {
+            min_length: 0.0,
+            max_length: f32::INFINITY,
+            max_angle: 180.0,
+            endpoints: false,
+            midline: false,
+            include: Vec::new(),
+            exclude: Vec::new(),
+        }
+    }
+}
+
+impl Filter {
+    //Return true if any streamline can be rejected
+    pub fn is_active(&self) -> bool {
+        self.min_length > 0.0 || self.max_length.is_finite() || self.max_angle < 180.0 ||
+            self.endpoints || self.midline || !self.include.is_empty() || !self.exclude.is_empty()
+    }
+
+    //Return which streamlines (in voxel coordinates) pass all filters together with the number of rejections per filter.
+    //The segmentation ndata and its cortex labels are used for the endpoint and midline filters.
+    pub fn apply(&self, nheader: &NIfTI1Header, ndata: &NIfTI1Data, cortex: &CortexLabels,
+                 streamlines: &[Streamline]) -> (Vec<bool>, FilterCounts) {
+        let spacing = [nheader.pixdim[1].abs(), nheader.pixdim[2].abs(), nheader.pixdim[3].abs()];
+
+        let mut counts = FilterCounts {
+            total: streamlines.len(),
+            kept: 0,
+            rejected: vec![("length", 0), ("angle", 0), ("endpoints", 0), ("midline", 0), ("include", 0), ("exclude", 0)],
+        };
+
+        let keep: Vec<bool> = streamlines.iter().map(|streamline| {
+            let failed = if self.min_length > 0.0 || self.max_length.is_finite() {
+                let l = length(streamline, spacing);
+                l < self.min_length || l > self.max_length
+            } else {
+                false
+            };
+            let fiber = trackvis::fiber(streamline);
+            let rejection = if failed {
+                Some(0)
+            } else if self.max_angle < 180.0 && max_angle(streamline, spacing) > self.max_angle {
+                Some(1)
+            } else if self.endpoints && !grey_matter_endpoints(&fiber, ndata, cortex) {
+                Some(2)
+            } else if self.midline && crosses_midline(&fiber, ndata, cortex) {
+                Some(3)
+            } else if self.include.iter().any(|mask| !fiber.iter().any(|p| value(mask, p) != 0.0)) {
+                Some(4)
+            } else if self.exclude.iter().any(|mask| fiber.iter().any(|p| value(mask, p) != 0.0)) {
+                Some(5)
+            } else {
+                None
+            };
+
+            match rejection {
+                Some(r) => {
+                    counts.rejected[r].1 += 1;
+                    false
+                },
+                None => true,
+            }
+        }).collect();
+
+        counts.kept = keep.iter().filter(|k| **k).count();
+        (keep, counts)
+    }
+}
+
+//Return the value of the first frame at a position, 0 outside of the volume
+fn value(data: &NIfTI1Data, pos: &Position) -> f32 {
+    if pos.x < 0 || pos.y < 0 || pos.z < 0 {
+        return 0.0;
+    }
+    data[0].get(pos.z as usize)
+        .and_then(|slice| slice.get(pos.y as usize))
+        .and_then(|row| row.get(pos.x as usize))
+        .cloned()
+        .unwrap_or(0.0)
+}
+
+//Return the vector between two points in voxel coordinates in mm
+fn step(from: &[f32; 3], to: &[f32; 3], spacing: [f32; 3]) -> [f32; 3] {
+    [(to[0] - from[0]) * spacing[0], (to[1] - from[1]) * spacing[1], (to[2] - from[2]) * spacing[2]]
+}
+
+fn norm(v: [f32; 3]) -> f32 {
+    (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt()
+}
+
+//Return the length of a streamline (in voxel coordinates) in mm
+pub fn length(streamline: &[[f32; 3]], spacing: [f32; 3]) -> f32 {
+    streamline.windows(2).map(|w| norm(step(&w[0], &w[1], spacing))).sum()
+}
+
+//Return the largest turning angle between consecutive segments of a streamline (in voxel coordinates) in degrees
+pub fn max_angle(streamline: &[[f32; 3]], spacing: [f32; 3]) -> f32 {
+    //Repeated points do not give a direction
+    let segments: Vec<[f32; 3]> = streamline.windows(2)
+        .map(|w| step(&w[0], &w[1], spacing))
+        .filter(|s| norm(*s) > 0.0)
+        .collect();
+
+    let mut angle = 0.0f32;
+    for s in segments.windows(2) {
+        let (a, b) = (s[0], s[1]);
+        let cos = (a[0] * b[0] + a[1] * b[1] + a[2] * b[2]) / (norm(a) * norm(b));
+        angle = angle.max(cos.clamp(-1.0, 1.0).acos().to_degrees());
+    }
+    angle
+}
+
+//Return true if both endpoints of a fiber are in the cortex or deep grey matter
+fn grey_matter_endpoints(fiber: &[Position], ndata: &NIfTI1Data, cortex: &CortexLabels) -> bool {
+    match (fiber.first(), fiber.last()) {
+        (Some(first), Some(last)) => cortex.is_grey_matter(value(ndata, first)) && cortex.is_grey_matter(value(ndata, last)),
+        _ => false,
+    }
+}
+
+//Return true if a fiber changes the hemisphere without passing the corpus callosum in between
+fn crosses_midline(fiber: &[Position], ndata: &NIfTI1Data, cortex: &CortexLabels) -> bool {
+    let mut current = 0;
+    let mut callosum = false;
+    for pos in fiber.iter() {
+        let label = value(ndata, pos);
+        if labels::is_callosum(label) {
+            callosum = true;
+            continue;
+        }
+
+        let hemisphere = cortex.hemisphere(label);
+        if hemisphere == 0 {
+            continue;
+        }
+        if current != 0 && hemisphere != current && !callosum {
+            return true;
+        }
+        current = hemisphere;
+        callosum = false;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn length_uses_the_voxel_coordinates_and_spacing() {
+        let streamline = [[0.0, 0.0, 0.0], [0.25, 0.0, 0.0], [0.25, 1.5, 0.0]];
+        assert_eq!(length(&streamline, [2.0, 2.0, 1.0]), 3.5);
+    }
+
+    #[test]
+    fn max_angle_is_measured_between_consecutive_segments() {
+        let streamline = [[0.0, 0.0, 0.0], [0.5, 0.0, 0.0], [0.5, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.5, 0.0]];
+        assert!((max_angle(&streamline, [1.0, 1.0, 1.0]) - 90.0).abs() < 1e-4);
+        assert_eq!(max_angle(&streamline[..4], [1.0, 1.0, 1.0]), 0.0);
+    }
+}
@@ -0,0 +1,102 @@
+//Label histogram of a voxel: the summed weight of the tracts of every label running through it
+//A voxel is only reached by a handful of labels, so the labels are kept in a small list.
+//The weights are summed in fixed point, so the sums do not depend on the order of the tracts.
+
+//Fixed point units per weight of 1
+const WEIGHT_SCALE: f64 = (1u64 << 20) as f64;
+
+#[derive(Clone, Copy)]
+pub struct LabelCount {
+    pub label: i32,
+    pub weight: i64,        //Summed weight in fixed point.
+    pub distance: f32,      //Smallest distance in mm to the cortex endpoint of a tract with this label.
+}
+
+#[derive(Clone, Default)]
+pub struct LabelHistogram {
+    pub counts: Vec<LabelCount>,
+    pub total: i64,                //Summed weight of all labels in fixed point.
+}
+
+impl LabelHistogram {
+    //Add the vote of a tract, distance is the distance of the voxel to the cortex endpoint of the tract
+    pub fn add(&mut self, label: i32, weight: f32, distance: f32) {
+        let weight = (weight as f64 * WEIGHT_SCALE).round() as i64;
+        self.add_count(LabelCount{label, weight, distance});
+    }
+
+    fn add_count(&mut self, count: LabelCount) {
+        match self.counts.iter_mut().find(|c| c.label == count.label) {
+            Some(c) => {
+                c.weight += count.weight;
+                c.distance = c.distance.min(count.distance);
+            },
+            None => self.counts.push(count),
+        }
+        self.total += count.weight;
+    }
+
+    //Add the votes of another histogram
+    pub fn merge(&mut self, other: &LabelHistogram) {
+        for count in other.counts.iter() {
+            self.add_count(*count);
+        }
+    }
+
+    //Return the count of a label
+    pub fn get(&self, label: i32) -> Option<&LabelCount> {
+        self.counts.iter().find(|c| c.label == label)
+    }
+
+    //Return the probability of a label
+    pub fn prob(&self, label: i32) -> f32 {
+        if self.total <= 0 {
+            return 0.0;
+        }
+        match self.get(label) {
+            Some(count) => (count.weight as f64 / self.total as f64) as f32,
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_sums_the_weights_and_keeps_the_nearest_endpoint() {
+        let mut histogram = LabelHistogram::default();
+        histogram.add(1001, 1.0, 4.0);
+        histogram.add(1002, 0.5, 1.0);
+        histogram.add(1001, 2.0, 2.0);
+        assert_eq!(histogram.counts.len(), 2);
+        assert_eq!(histogram.get(1001).unwrap().weight, (3.0 * WEIGHT_SCALE) as i64);
+        assert_eq!(histogram.get(1001).unwrap().distance, 2.0);
+        assert_eq!(histogram.prob(1001), 3.0 / 3.5);
+        assert_eq!(histogram.prob(1002), 0.5 / 3.5);
+        assert_eq!(histogram.prob(1003), 0.0);
+        assert_eq!(LabelHistogram::default().prob(1001), 0.0);
+    }
+
+    #[test]
+    fn merge_does_not_depend_on_the_order() {
+        let weights = [0.1, 0.7, 1e-3, 2.5, 0.3];
+        let mut forward = LabelHistogram::default();
+        for (i, w) in weights.iter().enumerate() {
+            let mut chunk = LabelHistogram::default();
+            chunk.add(1001 + (i % 2) as i32, *w, i as f32);
+            forward.merge(&chunk);
+        }
+        let mut backward = LabelHistogram::default();
+        for (i, w) in weights.iter().enumerate().rev() {
+            backward.add(1001 + (i % 2) as i32, *w, i as f32);
+        }
+        assert_eq!(forward.total, backward.total);
+        for label in [1001, 1002].iter() {
+            assert_eq!(forward.get(*label).unwrap().weight, backward.get(*label).unwrap().weight);
+            assert_eq!(forward.get(*label).unwrap().distance, backward.get(*label).unwrap().distance);
+            assert_eq!(forward.prob(*label), backward.prob(*label));
+        }
+    }
+}
@@ -0,0 +1,126 @@
+//Label values of the FreeSurfer segmentation (aseg, aparc+aseg) and their classification
+pub static LH_WM_LABEL: f32 = 2.0;
+pub static RH_WM_LABEL: f32 = 41.0;
+pub static LH_CORTEX_LABEL: f32 = 3.0;
+pub static RH_CORTEX_LABEL: f32 = 42.0;
+pub static LH_UNSEGMENTED_WM_LABEL: f32 = 5001.0;
+pub static RH_UNSEGMENTED_WM_LABEL: f32 = 5002.0;
+
+//FreeSurfer numbers the white matter below a cortex region 2000 higher (1001 -> 3001, 11101 -> 13101)
+pub static WM_OFFSET: i32 = 2000;
+
+//Hemisphere codes, also used as values of the hemisphere mask
+pub static LEFT_HEMISPHERE: i32 = 1;
+pub static RIGHT_HEMISPHERE: i32 = 2;
+
+//Deep grey matter: thalamus, caudate, putamen, pallidum, hippocampus, amygdala, accumbens and ventral DC
+static LH_DEEP_GM_LABELS: &[f32] = &[10.0, 11.0, 12.0, 13.0, 17.0, 18.0, 26.0, 28.0];
+static RH_DEEP_GM_LABELS: &[f32] = &[49.0, 50.0, 51.0, 52.0, 53.0, 54.0, 58.0, 60.0];
+
+//Return true for the corpus callosum
+pub fn is_callosum(label: f32) -> bool {
+    (251.0..=255.0).contains(&label)
+}
+
+//Return true for the white matter labels we want to color, including the corpus callosum
+pub fn is_white_matter(label: f32) -> bool {
+    label == LH_WM_LABEL || label == RH_WM_LABEL || is_callosum(label)
+}
+
+//Return true for the unparcellated cortex and the deep grey matter
+pub fn is_grey_matter(label: f32) -> bool {
+    label == LH_CORTEX_LABEL || label == RH_CORTEX_LABEL ||
+        LH_DEEP_GM_LABELS.contains(&label) || RH_DEEP_GM_LABELS.contains(&label)
+}
+
+//Return the hemisphere of a cerebral label, 0 if it belongs to none (e.g. corpus callosum, brain stem)
+pub fn hemisphere(label: f32) -> i32 {
+    let left = label == LH_WM_LABEL || label == LH_CORTEX_LABEL || label == LH_UNSEGMENTED_WM_LABEL ||
+        LH_DEEP_GM_LABELS.contains(&label) || (1000.0..2000.0).contains(&label) || (3000.0..4000.0).contains(&label);
+    let right = label == RH_WM_LABEL || label == RH_CORTEX_LABEL || label == RH_UNSEGMENTED_WM_LABEL ||
+        RH_DEEP_GM_LABELS.contains(&label) || (2000.0..3000.0).contains(&label) || (4000.0..5000.0).contains(&label);
+    if left {
+        LEFT_HEMISPHERE
+    } else if right {
+        RIGHT_HEMISPHERE
+    } else {
+        0
+    }
+}
+
+//Labels of the cortex regions of both hemispheres, by default the Desikan-Killiany atlas (1001-1035, 2001-2035)
+#[derive(Clone)]
+pub struct CortexLabels {
+    left: Vec<i32>,     //Sorted.
+    right: Vec<i32>,    //Sorted.
+}
+
+impl Default for CortexLabels {
+    fn default() -> CortexLabels {
+        CortexLabels {
+            left: (1001..1036).collect(),
+            right: (2001..2036).collect(),
+        }
+    }
+}
+
+impl CortexLabels {
+    pub fn new(left: &[i32], right: &[i32]) -> CortexLabels {
+        let mut labels = CortexLabels { left: left.to_vec(), right: right.to_vec() };
+        labels.left.sort();
+        labels.right.sort();
+        labels
+    }
+
+    //Return true for the cortex labels
+    pub fn contains(&self, label: f32) -> bool {
+        self.hemisphere_of_cortex(label) != 0
+    }
+
+    fn hemisphere_of_cortex(&self, label: f32) -> i32 {
+        if self.left.binary_search(&(label as i32)).is_ok() {
+            LEFT_HEMISPHERE
+        } else if self.right.binary_search(&(label as i32)).is_ok() {
+            RIGHT_HEMISPHERE
+        } else {
+            0
+        }
+    }
+
+    //Return the hemisphere of a cerebral label, the cortex labels belong to the hemisphere they are given for
+    pub fn hemisphere(&self, label: f32) -> i32 {
+        match self.hemisphere_of_cortex(label) {
+            0 => hemisphere(label),
+            h => h,
+        }
+    }
+
+    //Return true for the cortex labels, the unparcellated cortex and the deep grey matter
+    pub fn is_grey_matter(&self, label: f32) -> bool {
+        self.contains(label) || is_grey_matter(label)
+    }
+
+    //Return true if no cortex label renumbered by WM_OFFSET is itself a cortex label
+    pub fn offset_is_distinct(&self) -> bool {
+        !self.left.iter().chain(self.right.iter()).any(|l| self.contains((l + WM_OFFSET) as f32))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cortex_labels_give_their_hemisphere() {
+        let destrieux = CortexLabels::new(&[11175, 11101], &[12101, 12175]);
+        assert!(destrieux.contains(11101.0) && destrieux.contains(12175.0));
+        assert!(!destrieux.contains(1005.0));
+        assert_eq!(destrieux.hemisphere(12101.0), RIGHT_HEMISPHERE);
+        assert_eq!(destrieux.hemisphere(11175.0), LEFT_HEMISPHERE);
+        assert_eq!(destrieux.hemisphere(LH_WM_LABEL), LEFT_HEMISPHERE);
+        assert!(destrieux.is_grey_matter(11101.0) && destrieux.is_grey_matter(RH_CORTEX_LABEL));
+        assert!(!destrieux.is_grey_matter(RH_WM_LABEL));
+        assert!(destrieux.offset_is_distinct() && CortexLabels::default().offset_is_distinct());
+        assert!(!CortexLabels::new(&[1001], &[3001]).offset_is_distinct());
+    }
+}
@@ -0,0 +1,29 @@
+//White matter parcellation by the cortex labels of tractography streamlines
+//The parcellation module holds the algorithm, the other modules read and write volumes (NIfTI1, MGH/MGZ),
+//tractograms (TrackVis, MRtrix, TRX, VTK), lookup tables, label statistics and along-tract profiles. The capi module is the C interface of the shared library.
+extern crate flate2;
+extern crate zip;
+
+pub mod capi;
+pub mod distance;
+pub mod filter;
+pub mod histogram;
+pub mod labels;
+pub mod lut;
+pub mod mgh;
+pub mod nifti;
+mod parallel;
+pub mod parcellation;
+pub mod profile;
+pub mod stats;
+pub mod tck;
+#[cfg(test)]
+mod test_util;
+pub mod trackvis;
+pub mod tractogram;
+pub mod trx;
+pub mod volume;
+pub mod vtk;
+
+pub use labels::CortexLabels;
+pub use parcellation::{EndpointRule, Kernel, Parcellation, ParcellationResult, Stats, TieBreak};
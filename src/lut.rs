@@ -0,0 +1,220 @@
+//Colour lookup tables for the parcellation (FreeSurferColorLUT and ITK-SNAP label descriptions)
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+//Names and colours of the aseg and Desikan-Killiany labels as in FreeSurferColorLUT.txt
+static ASEG: &[(i32, &str, [u8; 3])] = &[
+    (0, "Unknown", [0, 0, 0]),
+    (2, "Left-Cerebral-White-Matter", [245, 245, 245]),
+    (3, "Left-Cerebral-Cortex", [205, 62, 78]),
+    (4, "Left-Lateral-Ventricle", [120, 18, 134]),
+    (5, "Left-Inf-Lat-Vent", [196, 58, 250]),
+    (7, "Left-Cerebellum-White-Matter", [220, 248, 164]),
+    (8, "Left-Cerebellum-Cortex", [230, 148, 34]),
+    (10, "Left-Thalamus", [0, 118, 14]),
+    (11, "Left-Caudate", [122, 186, 220]),
+    (12, "Left-Putamen", [236, 13, 176]),
+    (13, "Left-Pallidum", [12, 48, 255]),
+    (14, "3rd-Ventricle", [204, 182, 142]),
+    (15, "4th-Ventricle", [42, 204, 164]),
+    (16, "Brain-Stem", [119, 159, 176]),
+    (17, "Left-Hippocampus", [220, 216, 20]),
+    (18, "Left-Amygdala", [103, 255, 255]),
+    (24, "CSF", [60, 60, 60]),
+    (26, "Left-Accumbens-area", [255, 165, 0]),
+    (28, "Left-VentralDC", [165, 42, 42]),
+    (30, "Left-vessel", [160, 32, 240]),
+    (31, "Left-choroid-plexus", [0, 200, 200]),
+    (41, "Right-Cerebral-White-Matter", [245, 245, 245]),
+    (42, "Right-Cerebral-Cortex", [205, 62, 78]),
+    (43, "Right-Lateral-Ventricle", [120, 18, 134]),
+    (44, "Right-Inf-Lat-Vent", [196, 58, 250]),
+    (46, "Right-Cerebellum-White-Matter", [220, 248, 164]),
+    (47, "Right-Cerebellum-Cortex", [230, 148, 34]),
+    (49, "Right-Thalamus", [0, 118, 14]),
+    (50, "Right-Caudate", [122, 186, 220]),
+    (51, "Right-Putamen", [236, 13, 176]),
+    (52, "Right-Pallidum", [13, 48, 255]),
+    (53, "Right-Hippocampus", [220, 216, 20]),
+    (54, "Right-Amygdala", [103, 255, 255]),
+    (58, "Right-Accumbens-area", [255, 165, 0]),
+    (60, "Right-VentralDC", [165, 42, 42]),
+    (62, "Right-vessel", [160, 32, 240]),
+    (63, "Right-choroid-plexus", [0, 200, 221]),
+    (72, "5th-Ventricle", [120, 190, 150]),
+    (77, "WM-hypointensities", [200, 70, 255]),
+    (80, "non-WM-hypointensities", [164, 108, 226]),
+    (85, "Optic-Chiasm", [234, 169, 30]),
+    (251, "CC_Posterior", [0, 0, 64]),
+    (252, "CC_Mid_Posterior", [0, 0, 112]),
+    (253, "CC_Central", [0, 0, 160]),
+    (254, "CC_Mid_Anterior", [0, 0, 208]),
+    (255, "CC_Anterior", [0, 0, 255]),
+    (5001, "Left-UnsegmentedWhiteMatter", [20, 30, 40]),
+    (5002, "Right-UnsegmentedWhiteMatter", [40, 30, 20]),
+];
+
+//Desikan-Killiany regions, the cortex labels are 1000 (lh) and 2000 (rh) plus the index
+static CORTEX: &[(&str, [u8; 3])] = &[
+    ("unknown", [25, 5, 25]),
+    ("bankssts", [25, 100, 40]),
+    ("caudalanteriorcingulate", [125, 100, 160]),
+    ("caudalmiddlefrontal", [100, 25, 0]),
+    ("corpuscallosum", [120, 70, 50]),
+    ("cuneus", [220, 20, 100]),
+    ("entorhinal", [220, 20, 10]),
+    ("fusiform", [180, 220, 140]),
+    ("inferiorparietal", [220, 60, 220]),
+    ("inferiortemporal", [180, 40, 120]),
+    ("isthmuscingulate", [140, 20, 140]),
+    ("lateraloccipital", [20, 30, 140]),
+    ("lateralorbitofrontal", [35, 75, 50]),
+    ("lingual", [225, 140, 140]),
+    ("medialorbitofrontal", [200, 35, 75]),
+    ("middletemporal", [160, 100, 50]),
+    ("parahippocampal", [20, 220, 60]),
+    ("paracentral", [60, 220, 60]),
+    ("parsopercularis", [220, 180, 140]),
+    ("parsorbitalis", [20, 100, 50]),
+    ("parstriangularis", [220, 60, 20]),
+    ("pericalcarine", [120, 100, 60]),
+    ("postcentral", [220, 20, 20]),
+    ("posteriorcingulate", [220, 180, 220]),
+    ("precentral", [60, 20, 220]),
+    ("precuneus", [160, 140, 180]),
+    ("rostralanteriorcingulate", [80, 20, 140]),
+    ("rostralmiddlefrontal", [75, 50, 125]),
+    ("superiorfrontal", [20, 220, 160]),
+    ("superiorparietal", [20, 180, 140]),
+    ("superiortemporal", [140, 220, 220]),
+    ("supramarginal", [80, 160, 20]),
+    ("frontalpole", [100, 0, 100]),
+    ("temporalpole", [70, 70, 70]),
+    ("transversetemporal", [150, 150, 200]),
+    ("insula", [255, 192, 32]),
+];
+
+//Return name and colour of a label. Cortex labels are ctx-lh/ctx-rh, their white matter versions
+//(3000/4000 range) wm-lh/wm-rh with the inverted colour. Unknown labels get a generated colour.
+pub fn lookup(label: i32) -> (String, [u8; 3]) {
+    if let Some(entry) = ASEG.iter().find(|e| e.0 == label) {
+        return (entry.1.to_string(), entry.2);
+    }
+
+    let (prefix, index) = match label {
+        1000..=1999 => ("ctx-lh-", label - 1000),
+        2000..=2999 => ("ctx-rh-", label - 2000),
+        3000..=3999 => ("wm-lh-", label - 3000),
+        4000..=4999 => ("wm-rh-", label - 4000),
+        _ => ("", -1),
+    };
+    if index >= 0 && (index as usize) < CORTEX.len() {
+        let region = &CORTEX[index as usize];
+        let color = if label >= 3000 {
+            [255 - region.1[0], 255 - region.1[1], 255 - region.1[2]]
+        } else {
+            region.1
+        };
+        return (format!("{}{}", prefix, region.0), color);
+    }
+
+    //Spread the colours of unknown labels with a simple hash
+    let hash = (label as u32).wrapping_mul(2654435761);
+    (format!("label-{}", label), [(hash >> 24) as u8, (hash >> 16) as u8, (hash >> 8) as u8])
+}
+
+//Return the sorted list of distinct labels in a volume
+pub fn labels(data: &[Vec<Vec<Vec<f32>>>]) -> Vec<i32> {
+    let mut labels: Vec<i32> = data.iter().flatten().flatten().flatten().map(|v| *v as i32).collect();
+    labels.sort();
+    labels.dedup();
+    labels
+}
+
+fn write_text(text: &str, file_name: &str) {
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+    if let Err(why) = file.write_all(text.as_bytes()) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+//Write a lookup table in the format of FreeSurferColorLUT.txt
+pub fn write_freesurfer(labels: &[i32], file_name: &str) {
+    let mut text = String::from("#No. Label Name:                            R   G   B   A\n");
+    for label in labels.iter() {
+        let (name, color) = lookup(*label);
+        text.push_str(&format!("{:<5} {:<40} {:>3} {:>3} {:>3} {:>3}\n", label, name, color[0], color[1], color[2], 0));
+    }
+    write_text(&text, file_name);
+}
+
+//Write an ITK-SNAP label description file
+pub fn write_itksnap(labels: &[i32], file_name: &str) {
+    let mut text = String::from("################################################\n\
+                                 # ITK-SnAP Label Description File\n\
+                                 # File format: \n\
+                                 # IDX   -R-  -G-  -B-  -A--  VIS MSH  LABEL\n\
+                                 ################################################\n");
+    for label in labels.iter() {
+        let (name, color) = lookup(*label);
+        let visible = if *label == 0 { 0 } else { 1 };
+        text.push_str(&format!("{:>5} {:>5} {:>4} {:>4} {:>8} {:>2} {:>2}    \"{}\"\n",
+                               label, color[0], color[1], color[2], visible, visible, visible, name));
+    }
+    write_text(&text, file_name);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::temp_file;
+
+    #[test]
+    fn lookup_matches_freesurfer_color_lut() {
+        assert_eq!(lookup(2), (String::from("Left-Cerebral-White-Matter"), [245, 245, 245]));
+        assert_eq!(lookup(1001), (String::from("ctx-lh-bankssts"), [25, 100, 40]));
+        assert_eq!(lookup(3001), (String::from("wm-lh-bankssts"), [230, 155, 215]));
+        assert_eq!(lookup(4035), (String::from("wm-rh-insula"), [0, 63, 223]));
+        assert_eq!(lookup(5002).0, "Right-UnsegmentedWhiteMatter");
+        assert_eq!(lookup(1036).0, "label-1036");
+        assert_eq!(lookup(1036), lookup(1036));
+    }
+
+    #[test]
+    fn labels_are_sorted_and_distinct() {
+        let data = vec![vec![vec![vec![3001.0, 2.0, 0.0, 3001.0]]]];
+        assert_eq!(labels(&data), vec![0, 2, 3001]);
+    }
+
+    #[test]
+    fn write_freesurfer_is_parsed_back() {
+        let file = temp_file("lut", "txt");
+        write_freesurfer(&[0, 3001, 4035], &file);
+        let text = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let rows: Vec<Vec<&str>> = text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split_whitespace().collect()).collect();
+        assert_eq!(rows, vec![vec!["0", "Unknown", "0", "0", "0", "0"],
+                              vec!["3001", "wm-lh-bankssts", "230", "155", "215", "0"],
+                              vec!["4035", "wm-rh-insula", "0", "63", "223", "0"]]);
+    }
+
+    #[test]
+    fn write_itksnap_is_parsed_back() {
+        let file = temp_file("itksnap", "txt");
+        write_itksnap(&[0, 3001], &file);
+        let text = fs::read_to_string(&file).unwrap();
+        fs::remove_file(&file).unwrap();
+
+        let rows: Vec<Vec<&str>> = text.lines().filter(|l| !l.starts_with('#')).map(|l| l.split_whitespace().collect()).collect();
+        assert_eq!(rows, vec![vec!["0", "0", "0", "0", "0", "0", "0", "\"Unknown\""],
+                              vec!["3001", "230", "155", "215", "1", "1", "1", "\"wm-lh-bankssts\""]]);
+    }
+}
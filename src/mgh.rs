@@ -0,0 +1,354 @@
+//Definitions of the FreeSurfer MGH/MGZ datatypes
+//MGH files are big-endian, MGZ files are gzip compressed MGH files.
+use std::fs::File;
+use std::io::prelude::*;
+use std::mem;
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+
+use nifti;
+use nifti::{NIfTI1Data, NIfTI1Header};
+
+//Size of the header, the data starts right after it
+const HEADER_SIZE: usize = 284;
+
+//Data types
+const MRI_UCHAR: i32 = 0;
+const MRI_INT: i32 = 1;
+const MRI_FLOAT: i32 = 3;
+const MRI_SHORT: i32 = 4;
+
+pub struct MghHeader {
+    pub version: i32,               //0   Version number. Current version is 1.
+    pub dim: [i32; 4],              //4   Width, height, depth and number of frames.
+    pub data_type: i32,             //20  Data type (uchar, int, float or short).
+    pub dof: i32,                   //24  Degrees of freedom.
+    pub good_ras_flag: i16,         //28  If 1 the spacing and orientation fields are valid.
+    pub spacing: [f32; 3],          //30  Voxel size.
+    pub mdc: [[f32; 3]; 3],         //42  Direction cosines of the x, y and z axis (xr, xa, xs, yr, ...).
+    pub c_ras: [f32; 3],            //78  RAS coordinate of the center voxel (Pxyz_c).
+}
+
+//Return the voxel to RAS transformation of the volume
+pub fn affine(header: &MghHeader) -> [[f32; 4]; 4] {
+    let mut m = [[0.0f32; 4]; 4];
+    for (r, row) in m.iter_mut().take(3).enumerate() {
+        for (c, spacing) in header.spacing.iter().enumerate() {
+            row[c] = header.mdc[c][r] * spacing;
+        }
+
+        //The center of the volume is at c_ras
+        let center: f32 = (0..3).map(|c| row[c] * header.dim[c] as f32 / 2.0).sum();
+        row[3] = header.c_ras[r] - center;
+    }
+    m[3][3] = 1.0;
+    m
+}
+
+//Convert the header to a NIfTI1 header with float data and the same voxel to RAS transformation
+pub fn nifti_header(header: &MghHeader) -> NIfTI1Header {
+    let affine = affine(header);
+
+    let mut nheader: NIfTI1Header = unsafe { mem::zeroed() };
+    nheader.sizeof_hdr = 348;
+    nheader.dim = [if header.dim[3] > 1 { 4 } else { 3 },
+                   header.dim[0] as u16, header.dim[1] as u16, header.dim[2] as u16, header.dim[3] as u16,
+                   1, 1, 1];
+    nheader.datatype = 16;
+    nheader.bitpix = 32;
+    nheader.pixdim = [1.0, header.spacing[0], header.spacing[1], header.spacing[2], 1.0, 1.0, 1.0, 1.0];
+    nheader.vox_offset = 352.0;
+    nheader.scl_slope = 1.0;
+    nheader.xyzt_units = 2 + 8;
+    nheader.sform_code = 1;
+    nheader.srow_x = affine[0];
+    nheader.srow_y = affine[1];
+    nheader.srow_z = affine[2];
+    nheader.magic = *b"n+1\0";
+    nheader
+}
+
+//Convert a NIfTI1 header to a MGH header with the same voxel to RAS transformation
+pub fn from_nifti_header(nheader: &NIfTI1Header) -> MghHeader {
+    let affine = nifti::affine(nheader);
+
+    let mut header = MghHeader {
+        version: 1,
+        dim: [nheader.dim[1] as i32, nheader.dim[2] as i32, nheader.dim[3] as i32, (nheader.dim[4] as i32).max(1)],
+        data_type: MRI_FLOAT,
+        dof: 0,
+        good_ras_flag: 1,
+        spacing: [0.0; 3],
+        mdc: [[0.0; 3]; 3],
+        c_ras: [0.0; 3],
+    };
+
+    //Split the transformation into voxel size and direction cosines
+    for (c, (spacing, mdc)) in header.spacing.iter_mut().zip(header.mdc.iter_mut()).enumerate() {
+        let column = [affine[0][c], affine[1][c], affine[2][c]];
+        let norm = (column[0] * column[0] + column[1] * column[1] + column[2] * column[2]).sqrt();
+        *spacing = norm;
+        for (r, value) in mdc.iter_mut().enumerate() {
+            *value = if norm > 0.0 { column[r] / norm } else if r == c { 1.0 } else { 0.0 };
+        }
+    }
+
+    //RAS coordinate of the center voxel
+    let center = [header.dim[0] as f32 / 2.0, header.dim[1] as f32 / 2.0, header.dim[2] as f32 / 2.0];
+    header.c_ras = nifti::apply_affine(&affine, center);
+    header
+}
+
+//Return true if the file name belongs to a compressed MGZ file
+fn compressed(file_name: &str) -> bool {
+    let lower = file_name.to_lowercase();
+    lower.ends_with(".mgz") || lower.ends_with(".mgh.gz")
+}
+
+//Parse the header at the beginning of the (uncompressed) data of a MGH file
+fn parse_header(in_data: &[u8], display: &str) -> MghHeader {
+    if in_data.len() < HEADER_SIZE {
+        panic!("Wrong header size!");
+    }
+
+    //Read header
+    let int = |pos: usize| i32::from_be_bytes([in_data[pos], in_data[pos + 1], in_data[pos + 2], in_data[pos + 3]]);
+    let float = |pos: usize| f32::from_be_bytes([in_data[pos], in_data[pos + 1], in_data[pos + 2], in_data[pos + 3]]);
+
+    let mut header = MghHeader {
+        version: int(0),
+        dim: [int(4), int(8), int(12), int(16)],
+        data_type: int(20),
+        dof: int(24),
+        good_ras_flag: i16::from_be_bytes([in_data[28], in_data[29]]),
+        spacing: [1.0; 3],
+        mdc: [[-1.0, 0.0, 0.0], [0.0, 0.0, -1.0], [0.0, 1.0, 0.0]],
+        c_ras: [0.0; 3],
+    };
+
+    //Check integrety
+    if header.version != 1 {
+        panic!("Unsupported MGH version {} in {}!", header.version, display);
+    }
+    if header.dim.iter().any(|d| *d <= 0 || *d > u16::MAX as i32) {
+        panic!("Wrong dimensions in {}!", display);
+    }
+
+    //Without valid orientation FreeSurfer assumes a coronal volume with 1 mm voxels
+    if header.good_ras_flag > 0 {
+        for (i, s) in header.spacing.iter_mut().enumerate() {
+            *s = float(30 + 4 * i);
+        }
+        for (c, column) in header.mdc.iter_mut().enumerate() {
+            for (r, value) in column.iter_mut().enumerate() {
+                *value = float(42 + 12 * c + 4 * r);
+            }
+        }
+        for (i, c) in header.c_ras.iter_mut().enumerate() {
+            *c = float(78 + 4 * i);
+        }
+    }
+    header
+}
+
+//Read the header of a MGH or MGZ file without its data
+pub fn read_header(file_name: &str) -> MghHeader {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let mut in_data: Vec<u8> = Vec::with_capacity(HEADER_SIZE);
+    let result = if compressed(file_name) {
+        GzDecoder::new(file).take(HEADER_SIZE as u64).read_to_end(&mut in_data)
+    } else {
+        file.take(HEADER_SIZE as u64).read_to_end(&mut in_data)
+    };
+    if let Err(why) = result {
+        panic!("Could not read header of {}: {}", display, why);
+    }
+
+    parse_header(&in_data, &display.to_string())
+}
+
+//Read a MGH or MGZ volume. The data is converted to float and returned in the NIfTI1 layout.
+pub fn read(file_name: &str) -> (MghHeader, NIfTI1Data) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let mut in_data: Vec<u8> = Vec::new();
+    let result = if compressed(file_name) {
+        GzDecoder::new(file).read_to_end(&mut in_data)
+    } else {
+        let mut file = file;
+        file.read_to_end(&mut in_data)
+    };
+    if let Err(why) = result {
+        panic!("Could not read data of {}: {}", display, why);
+    }
+
+    let header = parse_header(&in_data, &display.to_string());
+
+    //Read data
+    let (size, convert): (usize, fn(&[u8]) -> f32) = match header.data_type {
+        MRI_UCHAR => (1, |b| b[0] as f32),
+        MRI_INT => (4, |b| i32::from_be_bytes([b[0], b[1], b[2], b[3]]) as f32),
+        MRI_FLOAT => (4, |b| f32::from_be_bytes([b[0], b[1], b[2], b[3]])),
+        MRI_SHORT => (2, |b| i16::from_be_bytes([b[0], b[1]]) as f32),
+        _ => panic!("Unsupported data type {} in {}!", header.data_type, display),
+    };
+
+    let [nx, ny, nz, nt] = [header.dim[0] as usize, header.dim[1] as usize, header.dim[2] as usize, header.dim[3] as usize];
+    let data_size = nx * ny * nz * nt * size;
+    if in_data.len() < HEADER_SIZE + data_size {
+        panic!("Something went wrong while reading the data. Expected {} bytes, but got {} bytes",
+               data_size, in_data.len() - HEADER_SIZE);
+    }
+
+    //The x index runs fastest, the frame index slowest
+    let mut values = in_data[HEADER_SIZE..HEADER_SIZE + data_size].chunks(size).map(convert);
+    let mut data: NIfTI1Data = Vec::with_capacity(nt);
+    for _ in 0..nt {
+        let mut volume = Vec::with_capacity(nz);
+        for _ in 0..nz {
+            let mut slice = Vec::with_capacity(ny);
+            for _ in 0..ny {
+                slice.push(values.by_ref().take(nx).collect::<Vec<f32>>());
+            }
+            volume.push(slice);
+        }
+        data.push(volume);
+    }
+
+    (header, data)
+}
+
+//Write a volume as MGH or MGZ file (selected by the extension). Volumes with integer values only
+//(e.g. label images) are stored as int, all others as float.
+pub fn write(header: &MghHeader, data: &NIfTI1Data, file_name: &str) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let integer = data.iter().flatten().flatten().flatten()
+        .all(|v| v.fract() == 0.0 && *v >= i32::MIN as f32 && *v <= i32::MAX as f32);
+    let data_type = if integer { MRI_INT } else { MRI_FLOAT };
+
+    //Fill header
+    let mut out_data: Vec<u8> = Vec::with_capacity(HEADER_SIZE);
+    out_data.extend_from_slice(&header.version.to_be_bytes());
+    for d in header.dim.iter() {
+        out_data.extend_from_slice(&d.to_be_bytes());
+    }
+    out_data.extend_from_slice(&data_type.to_be_bytes());
+    out_data.extend_from_slice(&header.dof.to_be_bytes());
+    out_data.extend_from_slice(&header.good_ras_flag.to_be_bytes());
+    for s in header.spacing.iter() {
+        out_data.extend_from_slice(&s.to_be_bytes());
+    }
+    for v in header.mdc.iter().flatten() {
+        out_data.extend_from_slice(&v.to_be_bytes());
+    }
+    for c in header.c_ras.iter() {
+        out_data.extend_from_slice(&c.to_be_bytes());
+    }
+    out_data.resize(HEADER_SIZE, 0);
+
+    //Write every entry in data
+    for v in data.iter().flatten().flatten().flatten() {
+        if integer {
+            out_data.extend_from_slice(&(*v as i32).to_be_bytes());
+        } else {
+            out_data.extend_from_slice(&v.to_be_bytes());
+        }
+    }
+
+    let result = if compressed(file_name) {
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(&out_data).and_then(|_| encoder.finish().map(|_| ()))
+    } else {
+        let mut file = file;
+        file.write_all(&out_data)
+    };
+    if let Err(why) = result {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::{temp_file, test_header};
+
+    //Volume of the test header with the values f(index)
+    fn test_data(f: fn(usize) -> f32) -> NIfTI1Data {
+        let mut data: NIfTI1Data = vec![vec![vec![vec![0.0; 12]; 10]; 8]];
+        for (index, v) in data.iter_mut().flatten().flatten().flatten().enumerate() {
+            *v = f(index);
+        }
+        data
+    }
+
+    fn assert_same_header(a: &MghHeader, b: &MghHeader) {
+        assert_eq!(a.version, b.version);
+        assert_eq!(a.dim, b.dim);
+        assert_eq!(a.good_ras_flag, b.good_ras_flag);
+        assert_eq!(a.spacing, b.spacing);
+        assert_eq!(a.mdc, b.mdc);
+        assert_eq!(a.c_ras, b.c_ras);
+    }
+
+    #[test]
+    fn from_nifti_header_keeps_the_affine() {
+        let nheader = test_header();
+        let header = from_nifti_header(&nheader);
+        assert_eq!(header.dim, [12, 10, 8, 1]);
+        assert_eq!(header.spacing, [2.0, 2.0, 2.0]);
+        assert_eq!(header.mdc, [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]]);
+        //c_ras is the RAS coordinate of the voxel dim / 2
+        assert_eq!(header.c_ras, [78.0, -116.0, -64.0]);
+        assert_eq!(affine(&header), nifti::affine(&nheader));
+        assert_eq!(nifti::affine(&nifti_header(&header)), nifti::affine(&nheader));
+    }
+
+    #[test]
+    fn write_read_mgh_and_mgz() {
+        let header = from_nifti_header(&test_header());
+        let labels = test_data(|i| [0.0, 2.0, 41.0, 1003.0, 2005.0][i % 5]);
+        let values = test_data(|i| i as f32 * 0.25 - 100.0);
+
+        for extension in ["mgh", "mgz"].iter() {
+            for (name, data, data_type) in [("labels", &labels, MRI_INT), ("values", &values, MRI_FLOAT)].iter() {
+                let file = temp_file(name, extension);
+                write(&header, data, &file);
+                let only_header = read_header(&file);
+                let (header_read, data_read) = read(&file);
+                fs::remove_file(&file).unwrap();
+
+                assert_same_header(&header_read, &header);
+                assert_same_header(&only_header, &header);
+                assert_eq!(header_read.data_type, *data_type);
+                assert!(data_read == **data);
+            }
+        }
+    }
+}
@@ -8,6 +8,7 @@ use std::path::Path;
 pub type NIfTI1Data = Vec<Vec<Vec<Vec<f32>>>>;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct NIfTI1Header{
     pub sizeof_hdr: i32,           //0   Size of the header. Must be 348 (bytes).
     pub data_type: [u8; 10],       //4   Not used; compatibility with analyze.
@@ -79,7 +80,124 @@ impl New for NIfTI1Data {
     }
 }
 
-pub fn read(file_name: &str) -> (NIfTI1Header, NIfTI1Data){
+//Return the voxel to RAS (mm) transformation of the image.
+//The sform is used if it is set, otherwise the qform, otherwise the voxel sizes.
+pub fn affine(header: &NIfTI1Header) -> [[f32; 4]; 4] {
+    if header.sform_code > 0 {
+        return sform_affine(header);
+    }
+
+    let (dx, dy, dz) = (header.pixdim[1], header.pixdim[2], header.pixdim[3]);
+    if header.qform_code <= 0 {
+        return [[dx, 0.0, 0.0, 0.0],
+                [0.0, dy, 0.0, 0.0],
+                [0.0, 0.0, dz, 0.0],
+                [0.0, 0.0, 0.0, 1.0]];
+    }
+    qform_affine(header)
+}
+
+//Return the transformation stored in the sform fields, regardless of sform_code
+pub fn sform_affine(header: &NIfTI1Header) -> [[f32; 4]; 4] {
+    [header.srow_x, header.srow_y, header.srow_z, [0.0, 0.0, 0.0, 1.0]]
+}
+
+//Return the transformation stored in the qform fields, regardless of qform_code
+pub fn qform_affine(header: &NIfTI1Header) -> [[f32; 4]; 4] {
+    let (dx, dy, dz) = (header.pixdim[1], header.pixdim[2], header.pixdim[3]);
+
+    //Rotation matrix from the quaternion (b, c, d), the sign of the third column is stored in pixdim[0]
+    let (b, c, d) = (header.quatern_b, header.quatern_c, header.quatern_d);
+    let a = (1.0 - b * b - c * c - d * d).max(0.0).sqrt();
+    let qfac = if header.pixdim[0] < 0.0 { -1.0 } else { 1.0 };
+    let dz = dz * qfac;
+
+    [[(a * a + b * b - c * c - d * d) * dx, 2.0 * (b * c - a * d) * dy, 2.0 * (b * d + a * c) * dz, header.qoffset_x],
+     [2.0 * (b * c + a * d) * dx, (a * a + c * c - b * b - d * d) * dy, 2.0 * (c * d - a * b) * dz, header.qoffset_y],
+     [2.0 * (b * d - a * c) * dx, 2.0 * (c * d + a * b) * dy, (a * a + d * d - c * c - b * b) * dz, header.qoffset_z],
+     [0.0, 0.0, 0.0, 1.0]]
+}
+
+//Return the determinant of the rotation/scaling part of an affine transformation
+pub fn determinant(m: &[[f32; 4]; 4]) -> f32 {
+    m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+        - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+        + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+}
+
+//Invert an affine transformation (rotation/scaling part and translation)
+pub fn inverse_affine(m: &[[f32; 4]; 4]) -> [[f32; 4]; 4] {
+    let det = determinant(m);
+    if det == 0.0 {
+        panic!("The affine transformation of the image is not invertible!");
+    }
+
+    let mut inv = [[0.0f32; 4]; 4];
+    inv[0][0] = (m[1][1] * m[2][2] - m[1][2] * m[2][1]) / det;
+    inv[0][1] = (m[0][2] * m[2][1] - m[0][1] * m[2][2]) / det;
+    inv[0][2] = (m[0][1] * m[1][2] - m[0][2] * m[1][1]) / det;
+    inv[1][0] = (m[1][2] * m[2][0] - m[1][0] * m[2][2]) / det;
+    inv[1][1] = (m[0][0] * m[2][2] - m[0][2] * m[2][0]) / det;
+    inv[1][2] = (m[0][2] * m[1][0] - m[0][0] * m[1][2]) / det;
+    inv[2][0] = (m[1][0] * m[2][1] - m[1][1] * m[2][0]) / det;
+    inv[2][1] = (m[0][1] * m[2][0] - m[0][0] * m[2][1]) / det;
+    inv[2][2] = (m[0][0] * m[1][1] - m[0][1] * m[1][0]) / det;
+
+    //Translation is the negative rotated translation
+    for row in inv.iter_mut().take(3) {
+        row[3] = -(row[0] * m[0][3] + row[1] * m[1][3] + row[2] * m[2][3]);
+    }
+    inv[3][3] = 1.0;
+
+    inv
+}
+
+//Apply an affine transformation to a point
+pub fn apply_affine(m: &[[f32; 4]; 4], p: [f32; 3]) -> [f32; 3] {
+    let mut out = [0.0f32; 3];
+    for (r, o) in out.iter_mut().enumerate() {
+        *o = m[r][0] * p[0] + m[r][1] * p[1] + m[r][2] * p[2] + m[r][3];
+    }
+    out
+}
+
+//Return the volume of a voxel in mm^3, the unit of the voxel sizes is given by xyzt_units (mm if unknown)
+pub fn voxel_volume(header: &NIfTI1Header) -> f32 {
+    let scale = match header.xyzt_units & 0x07 {
+        1 => 1000.0,    //m
+        3 => 0.001,     //um
+        _ => 1.0,       //mm or unknown
+    };
+    (1..4).map(|i| header.pixdim[i].abs() * scale).product()
+}
+
+//Return the header of a float image with the dimensions (x, y, z, frames) and the voxel to RAS transformation
+pub fn header(dim: [u16; 4], affine: &[[f32; 4]; 4]) -> NIfTI1Header {
+    let mut header: NIfTI1Header = unsafe { mem::zeroed() };
+    header.sizeof_hdr = 348;
+    header.dim = [if dim[3] > 1 { 4 } else { 3 }, dim[0], dim[1], dim[2], dim[3].max(1), 1, 1, 1];
+    header.datatype = 16;
+    header.bitpix = 32;
+
+    //The voxel sizes are the lengths of the columns
+    header.pixdim = [1.0; 8];
+    for (c, pixdim) in header.pixdim[1..4].iter_mut().enumerate() {
+        *pixdim = (affine[0][c] * affine[0][c] + affine[1][c] * affine[1][c] + affine[2][c] * affine[2][c]).sqrt();
+    }
+
+    header.vox_offset = 352.0;
+    header.scl_slope = 1.0;
+    header.xyzt_units = 2 + 8;
+    header.sform_code = 1;
+    header.srow_x = affine[0];
+    header.srow_y = affine[1];
+    header.srow_z = affine[2];
+    header.magic = *b"n+1\0";
+    header
+}
+
+//Open a NIfTI1 file and read its header, the file is left at the beginning of the data
+fn open(file_name: &str) -> (File, NIfTI1Header){
 
     //Open file
     let path = Path::new(file_name);
@@ -108,7 +226,18 @@ pub fn read(file_name: &str) -> (NIfTI1Header, NIfTI1Data){
     if header.sizeof_hdr != 348 {
         panic!("Wrong header size!");
     }
-    
+    (file, header)
+}
+
+//Read the header of a NIfTI1 file without its data
+pub fn read_header(file_name: &str) -> NIfTI1Header{
+    open(file_name).1
+}
+
+pub fn read(file_name: &str) -> (NIfTI1Header, NIfTI1Data){
+    let (mut file, header) = open(file_name);
+    let display = Path::new(file_name).display();
+
     //Read data
     let mut in_data: Vec<u8> = Vec::new();
     let data_result = match file.read_to_end(&mut in_data){
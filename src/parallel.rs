@@ -0,0 +1,53 @@
+//Data parallel execution with scoped threads
+//The items are split into one contiguous block per thread and the results keep the order of the items,
+//so the results do not depend on the number of threads.
+use std::thread;
+
+//Return the number of threads to use by default, one per core
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+//Return the number of items per thread
+fn block_size(len: usize, threads: usize) -> usize {
+    len.div_ceil(threads.max(1)).max(1)
+}
+
+//Apply f to every item and return the results in the order of the items
+pub fn map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+    where T: Sync, R: Send, F: Fn(&T) -> R + Sync {
+    if threads <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let f = &f;
+    thread::scope(|scope| {
+        let handles: Vec<_> = items.chunks(block_size(items.len(), threads))
+            .map(|block| scope.spawn(move || block.iter().map(f).collect::<Vec<R>>()))
+            .collect();
+        handles.into_iter().flat_map(|h| h.join().expect("A worker thread panicked!")).collect()
+    })
+}
+
+//Apply f to every item together with its index
+pub fn for_each_mut<T, F>(items: &mut [T], threads: usize, f: F)
+    where T: Send, F: Fn(usize, &mut T) + Sync {
+    if threads <= 1 || items.len() <= 1 {
+        for (i, item) in items.iter_mut().enumerate() {
+            f(i, item);
+        }
+        return;
+    }
+
+    let f = &f;
+    let size = block_size(items.len(), threads);
+    thread::scope(|scope| {
+        for (b, block) in items.chunks_mut(size).enumerate() {
+            scope.spawn(move || {
+                for (i, item) in block.iter_mut().enumerate() {
+                    f(b * size + i, item);
+                }
+            });
+        }
+    });
+}
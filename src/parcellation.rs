@@ -0,0 +1,820 @@
+//White matter parcellation by the cortex labels of the tracts running through every voxel
+//Every tract votes for the cortex label at its endpoint in all voxels it passes. The label of a voxel
+//is chosen from its votes together with the votes of the neighbouring voxels.
+use std::collections::{BTreeMap, HashMap};
+
+use distance;
+use filter::{Filter, FilterCounts};
+use histogram::{LabelCount, LabelHistogram};
+use labels;
+use labels::CortexLabels;
+use nifti::{NIfTI1Data, NIfTI1Header, New};
+use parallel;
+use trackvis;
+use trackvis::{Position, Streamline};
+
+//Number of tracts per chunk of the tract pass. The chunks are merged in their order,
+//so the result does not depend on the number of threads.
+static TRACT_CHUNK: usize = 10000;
+
+//Label histograms of the voxels, split into shards by slice so they can be merged in parallel
+type Histograms = Vec<HashMap<Position, LabelHistogram>>;
+
+//Rule to choose between labels with the same probability
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum TieBreak {
+    Lowest,     //The lowest label.
+    Votes,      //The label with the most direct votes (summed weight) in the voxel.
+    Endpoint,   //The label with the nearest cortex endpoint of a tract.
+}
+
+//Neighbourhood of a voxel whose votes are added to the votes of the voxel
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Kernel {
+    None,       //Only the votes of the voxel itself.
+    Octant,     //The 7 neighbours with x, y and z lower or equal, the original neighbourhood of wmparc.
+    Full,       //All 26 neighbours.
+}
+
+//Cortex voxel along a tract that gives the label of the tract
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum EndpointRule {
+    First,      //The first cortex voxel in the order of the points.
+    Last,       //The last cortex voxel in the order of the points.
+}
+
+//Settings of the parcellation, built up from the defaults:
+//  let result = Parcellation::new().hemisphere(true).threads(4).run(&nheader, &ndata, &tracts, None);
+pub struct Parcellation {
+    cortex_labels: CortexLabels,        //Labels of the cortex regions.
+    wm_labels: Vec<i32>,                //Labels of the white matter that is parcellated, sorted.
+    kernel: Kernel,
+    endpoint_rule: EndpointRule,
+    tie_break: TieBreak,
+    hemisphere: bool,                   //Reject votes of cortex labels from the other hemisphere.
+    hemisphere_mask: Option<NIfTI1Data>,//Hemisphere of every voxel, otherwise given by the white matter label.
+    depth_min: f32,                     //Depth band of the labelled white matter in mm.
+    depth_max: f32,
+    depth_map: bool,                    //Return the depth of the white matter.
+    wm_offset: bool,                    //Renumber the labels by labels::WM_OFFSET (3000/4000 range for Desikan-Killiany).
+    merge: bool,                        //Write the labels into a copy of the segmentation.
+    labelwm: bool,                      //Label the remaining white matter 5001/5002.
+    filter: Filter,
+    threads: usize,
+}
+
+//Statistics of a run
+pub struct Stats {
+    pub tracts: usize,                  //Number of tracts.
+    pub kept_tracts: usize,             //Tracts that passed the filters.
+    pub labelled_tracts: usize,         //Kept tracts that reach the cortex.
+    pub votes: usize,                   //Label of a tract in a voxel.
+    pub rejected_votes: usize,          //Votes rejected by the hemisphere constraint.
+    pub voted_voxels: usize,            //Voxels a label was chosen for.
+    pub non_wm_voxels: usize,           //Voted voxels skipped because they are not white matter (or outside the volume).
+    pub depth_voxels: usize,            //Voted white matter voxels skipped because they are outside the depth band.
+    pub labelled_voxels: usize,         //Voxels labelled in the output volume.
+    pub filter: Option<FilterCounts>,   //Rejections per filter, if any filter is active.
+}
+
+pub struct ParcellationResult {
+    pub volume: NIfTI1Data,             //Parcellation in the grid of the segmentation.
+    pub labels: BTreeMap<Position, i32>,//Chosen label of every voxel with votes, before masking the white matter.
+    pub labelled: Vec<Position>,        //Voxels labelled in the output volume.
+    pub tract_labels: Vec<i32>,         //Cortex label of every tract, 0 if it does not reach the cortex or is filtered.
+    pub keep: Vec<bool>,                //True for the tracts that passed the filters.
+    pub depth: Option<NIfTI1Data>,      //Distance of the white matter to the cortex in mm, 0 elsewhere.
+    pub stats: Stats,
+}
+
+//Votes of a chunk of tracts
+struct Votes {
+    histograms: Histograms,
+    tract_labels: Vec<i32>,
+    votes: usize,
+    rejected_votes: usize,
+}
+
+impl Default for Parcellation {
+    fn default() -> Parcellation {
+        Parcellation {
+            cortex_labels: CortexLabels::default(),
+            wm_labels: vec![labels::LH_WM_LABEL as i32, labels::RH_WM_LABEL as i32, 251, 252, 253, 254, 255],
+            kernel: Kernel::Octant,
+            endpoint_rule: EndpointRule::Last,
+            tie_break: TieBreak::Lowest,
+            hemisphere: false,
+            hemisphere_mask: None,
+            depth_min: 0.0,
+            depth_max: f32::INFINITY,
+            depth_map: false,
+            wm_offset: false,
+            merge: false,
+            labelwm: false,
+            filter: Filter::default(),
+            threads: parallel::default_threads(),
+        }
+    }
+}
+
+impl Parcellation {
+    pub fn new() -> Parcellation {
+        Parcellation::default()
+    }
+
+    //Labels of the cortex regions of both hemispheres, by default the Desikan-Killiany atlas (1001-1035, 2001-2035).
+    //They are also used by the endpoint and midline filters and to find the hemisphere of a cortex label.
+    pub fn cortex_labels(mut self, cortex_labels: CortexLabels) -> Parcellation {
+        self.cortex_labels = cortex_labels;
+        self
+    }
+
+    //Labels of the white matter that is parcellated, by default 2, 41 and the corpus callosum (251-255)
+    pub fn wm_labels(mut self, wm_labels: &[i32]) -> Parcellation {
+        self.wm_labels = wm_labels.to_vec();
+        self.wm_labels.sort();
+        self
+    }
+
+    pub fn kernel(mut self, kernel: Kernel) -> Parcellation {
+        self.kernel = kernel;
+        self
+    }
+
+    pub fn endpoint_rule(mut self, endpoint_rule: EndpointRule) -> Parcellation {
+        self.endpoint_rule = endpoint_rule;
+        self
+    }
+
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Parcellation {
+        self.tie_break = tie_break;
+        self
+    }
+
+    pub fn hemisphere(mut self, hemisphere: bool) -> Parcellation {
+        self.hemisphere = hemisphere;
+        self
+    }
+
+    //Hemisphere of every voxel (1 left, 2 right) in the grid of the segmentation, implies the hemisphere constraint
+    pub fn hemisphere_mask(mut self, mask: NIfTI1Data) -> Parcellation {
+        self.hemisphere = true;
+        self.hemisphere_mask = Some(mask);
+        self
+    }
+
+    //Only label the white matter between min and max mm from the cortex
+    pub fn depth_band(mut self, min: f32, max: f32) -> Parcellation {
+        if !(min >= 0.0 && min <= max) {
+            panic!("The depth band has to be 0 <= min <= max, but got min {} and max {}!", min, max);
+        }
+        self.depth_min = min;
+        self.depth_max = max;
+        self
+    }
+
+    pub fn depth_map(mut self, depth_map: bool) -> Parcellation {
+        self.depth_map = depth_map;
+        self
+    }
+
+    //Renumber the labels 2000 higher like FreeSurfer (1001 -> 3001, 11101 -> 13101),
+    //a cortex label plus 2000 must not be a cortex label itself
+    pub fn wm_offset(mut self, wm_offset: bool) -> Parcellation {
+        self.wm_offset = wm_offset;
+        self
+    }
+
+    //Write the labels into a copy of the segmentation, implies wm_offset so they do not clash with the cortex labels
+    pub fn merge(mut self, merge: bool) -> Parcellation {
+        self.merge = merge;
+        self
+    }
+
+    //Label the white matter like mri_aparc2aseg --labelwm, implies merge and wm_offset
+    pub fn labelwm(mut self, labelwm: bool) -> Parcellation {
+        self.labelwm = labelwm;
+        self.merge |= labelwm;
+        self.wm_offset |= labelwm;
+        self
+    }
+
+    pub fn filter(mut self, filter: Filter) -> Parcellation {
+        self.filter = filter;
+        self
+    }
+
+    //Number of threads, the result does not depend on it
+    pub fn threads(mut self, threads: usize) -> Parcellation {
+        self.threads = threads.max(1);
+        self
+    }
+
+    fn is_white_matter(&self, label: f32) -> bool {
+        self.wm_labels.binary_search(&(label as i32)).is_ok()
+    }
+
+    fn depth_band_active(&self) -> bool {
+        self.depth_min > 0.0 || self.depth_max.is_finite()
+    }
+
+    //Parcellate the white matter of the segmentation ndata (e.g. aparc+aseg) by the tracts given in voxel coordinates.
+    //Every tract votes with its weight, all tracts have the weight 1 without weights.
+    pub fn run(&self, nheader: &NIfTI1Header, ndata: &NIfTI1Data, tracts: &[Streamline], weights: Option<&[f32]>) -> ParcellationResult {
+        let threads = self.threads;
+
+        let unit_weights;
+        let weights = match weights {
+            Some(w) => w,
+            None => {
+                unit_weights = vec![1.0; tracts.len()];
+                &unit_weights
+            },
+        };
+        if weights.len() != tracts.len() {
+            panic!("Expected {} weights, but got {} weights", tracts.len(), weights.len());
+        }
+        if let Some(ref mask) = self.hemisphere_mask {
+            if dimensions(mask) != dimensions(ndata) {
+                panic!("The hemisphere mask does not match the dimensions of the segmentation!");
+            }
+        }
+        if (self.wm_offset || self.merge) && !self.cortex_labels.offset_is_distinct() {
+            panic!("The cortex labels plus {} overlap the cortex labels, so the white matter labels cannot be renumbered!", labels::WM_OFFSET);
+        }
+
+        //Only the tracts that pass the filters are labelled
+        let (keep, filter_counts) = if self.filter.is_active() {
+            let (keep, counts) = self.filter.apply(nheader, ndata, &self.cortex_labels, tracts);
+            (keep, Some(counts))
+        } else {
+            (vec![true; tracts.len()], None)
+        };
+
+        //First iteration through the fibers. Build up the label histograms chunk by chunk, every thread
+        //votes for one chunk per round and the votes are merged in the order of the chunks
+        let spacing = [nheader.pixdim[1].abs(), nheader.pixdim[2].abs(), nheader.pixdim[3].abs()];
+        let mut histograms: Histograms = vec![HashMap::new(); threads];
+        let mut tract_labels: Vec<i32> = Vec::with_capacity(tracts.len());
+        let mut votes = 0usize;
+        let mut rejected_votes = 0usize;
+
+        let chunks: Vec<usize> = (0..tracts.len().div_ceil(TRACT_CHUNK)).collect();
+        for round in chunks.chunks(threads) {
+            let round_votes = parallel::map(round, threads, |c| {
+                let range = c * TRACT_CHUNK..((c + 1) * TRACT_CHUNK).min(tracts.len());
+                self.vote(&tracts[range.clone()], &keep[range.clone()], &weights[range], ndata, spacing)
+            });
+
+            parallel::for_each_mut(&mut histograms, threads, |s, shard| {
+                for chunk in round_votes.iter() {
+                    for (pos, histogram) in chunk.histograms[s].iter() {
+                        shard.entry(*pos).or_default().merge(histogram);
+                    }
+                }
+            });
+
+            for chunk in round_votes {
+                tract_labels.extend(chunk.tract_labels);
+                votes += chunk.votes;
+                rejected_votes += chunk.rejected_votes;
+            }
+        }
+
+        //Choose label for every voxel
+        let offsets = self.offsets();
+        let voxels: Vec<(&Position, &LabelHistogram)> = histograms.iter().flatten().collect();
+        let chosen = parallel::map(&voxels, threads, |v| choose_label(v.0, v.1, &histograms, &offsets, self.tie_break));
+
+        let mut final_labels: BTreeMap<Position, i32> = BTreeMap::new();
+        for ((pos, _), label) in voxels.iter().zip(chosen) {
+            if label > 0 {
+                final_labels.insert(**pos, label);
+            }
+        }
+
+        //Distance of every voxel to the cortex in mm, only needed for the depth band and the depth map
+        let mut depth = if self.depth_band_active() || self.depth_map {
+            let spacing = [nheader.pixdim[1], nheader.pixdim[2], nheader.pixdim[3]];
+            Some(distance::distance_map(ndata, spacing, |l| self.cortex_labels.contains(l)))
+        } else {
+            None
+        };
+
+        //Write the groups to nifti data. When merging all other labels of the input are kept.
+        let mut outdata = if self.merge { ndata.clone() } else { NIfTI1Data::init(nheader) };
+        let mut labelled: Vec<Position> = Vec::new();
+        let mut non_wm_voxels = 0usize;
+        let mut depth_voxels = 0usize;
+        for (pos, label) in final_labels.iter(){
+            //Make sure we have a label that we want to color
+            let curr_label = value(ndata, pos).unwrap_or(0.0);
+            if !self.is_white_matter(curr_label) {
+                non_wm_voxels += 1;
+                continue;
+            }
+
+            //Only label the white matter within the depth band
+            if let Some(ref d) = depth {
+                let voxel_depth = d[0][pos.z as usize][pos.y as usize][pos.x as usize];
+                if voxel_depth < self.depth_min || voxel_depth > self.depth_max {
+                    depth_voxels += 1;
+                    continue;
+                }
+            }
+
+            //FreeSurfer numbers the white matter below a cortex region 2000 higher (1001 -> 3001)
+            let label = if self.wm_offset || self.merge { *label + labels::WM_OFFSET } else { *label };
+            outdata[0][pos.z as usize][pos.y as usize][pos.x as usize] = label as f32;
+            labelled.push(*pos);
+        }
+
+        //The white matter without cortex label is assigned to its hemisphere
+        if self.labelwm {
+            for voxel in outdata[0].iter_mut().flatten().flatten() {
+                if *voxel == labels::LH_WM_LABEL {
+                    *voxel = labels::LH_UNSEGMENTED_WM_LABEL;
+                } else if *voxel == labels::RH_WM_LABEL {
+                    *voxel = labels::RH_UNSEGMENTED_WM_LABEL;
+                }
+            }
+        }
+
+        //The depth of the white matter, all other voxels are 0
+        if !self.depth_map {
+            depth = None;
+        }
+        if let Some(ref mut d) = depth {
+            for (label, value) in ndata[0].iter().flatten().flatten().zip(d[0].iter_mut().flatten().flatten()) {
+                if !self.is_white_matter(*label) || value.is_infinite() {
+                    *value = 0.0;
+                }
+            }
+        }
+
+        let stats = Stats {
+            tracts: tracts.len(),
+            kept_tracts: keep.iter().filter(|k| **k).count(),
+            labelled_tracts: tract_labels.iter().filter(|l| **l > 0).count(),
+            votes,
+            rejected_votes,
+            voted_voxels: final_labels.len(),
+            non_wm_voxels,
+            depth_voxels,
+            labelled_voxels: labelled.len(),
+            filter: filter_counts,
+        };
+
+        ParcellationResult {
+            volume: outdata,
+            labels: final_labels,
+            labelled,
+            tract_labels,
+            keep,
+            depth,
+            stats,
+        }
+    }
+
+    //Collect the votes of a chunk of tracts
+    fn vote(&self, tracts: &[Streamline], keep: &[bool], weights: &[f32], ndata: &NIfTI1Data, spacing: [f32; 3]) -> Votes {
+        let shards = self.threads;
+        let mut chunk = Votes {
+            histograms: vec![HashMap::new(); shards],
+            tract_labels: Vec::with_capacity(tracts.len()),
+            votes: 0,
+            rejected_votes: 0,
+        };
+
+        for ((tract, kept), weight) in tracts.iter().zip(keep.iter()).zip(weights.iter().cloned()) {
+            if !kept {
+                chunk.tract_labels.push(0);
+                continue;
+            }
+            //Every point votes for its nearest voxel
+            let tract = trackvis::fiber(tract);
+
+            //The group is determined by the value in the segmentation file (e.g. asec+aparc)
+            //at the position of the first or last cortex element of the fiber
+            let mut label = -1.0;
+            let mut endpoint = Position{x: 0, y: 0, z: 0};
+
+            //Determine label
+            for pos in tract.iter() {
+                let group = value(ndata, pos).unwrap_or(0.0);
+                if self.cortex_labels.contains(group) {
+                    label = group;
+                    endpoint = *pos;
+                    if self.endpoint_rule == EndpointRule::First {
+                        break;
+                    }
+                }
+            }
+
+            chunk.tract_labels.push(if label > -1.0 { label as i32 } else { 0 });
+
+            //Add label to every voxel in tract if there was a associating cortex label
+            if label > -1.0{
+                for pos in tract.iter() {
+                    //Points outside of the volume do not vote
+                    let voxel_label = match value(ndata, pos) {
+                        Some(l) => l,
+                        None => continue,
+                    };
+                    chunk.votes += 1;
+
+                    //Skip voxels of the other hemisphere
+                    if self.hemisphere {
+                        let mask = self.hemisphere_mask.as_ref().and_then(|m| value(m, pos));
+                        let voxel = voxel_hemisphere(voxel_label, mask);
+                        if voxel != 0 && voxel != self.cortex_labels.hemisphere(label) {
+                            chunk.rejected_votes += 1;
+                            continue;
+                        }
+                    }
+
+                    let distance = rel_dist((pos.x - endpoint.x) as f32 * spacing[0],
+                                            (pos.y - endpoint.y) as f32 * spacing[1],
+                                            (pos.z - endpoint.z) as f32 * spacing[2]);
+                    chunk.histograms[shard(pos, shards)].entry(*pos).or_default().add(label as i32, weight, distance);
+                }
+            }
+        }
+
+        chunk
+    }
+
+    //Return the offsets of the neighbours together with their weight (inverse distance)
+    fn offsets(&self) -> Vec<([i32; 3], f32)> {
+        let range = match self.kernel {
+            Kernel::None => return Vec::new(),
+            Kernel::Octant => -1..1,
+            Kernel::Full => -1..2,
+        };
+
+        let mut offsets = Vec::new();
+        for iz in range.clone() {
+            for iy in range.clone() {
+                for ix in range.clone() {
+                    if iz == 0 && iy == 0 && ix == 0 {
+                        //Skip current position
+                        continue;
+                    }
+                    offsets.push(([ix, iy, iz], 1.0 / rel_dist(ix as f32, iy as f32, iz as f32)));
+                }
+            }
+        }
+        offsets
+    }
+}
+
+//Return the dimensions of the first frame of a volume
+fn dimensions(data: &NIfTI1Data) -> (usize, usize, usize) {
+    let nz = data[0].len();
+    let ny = data[0].first().map_or(0, |s| s.len());
+    let nx = data[0].first().and_then(|s| s.first()).map_or(0, |r| r.len());
+    (nx, ny, nz)
+}
+
+//Return the value of the first frame at a position, None outside of the volume
+fn value(data: &NIfTI1Data, pos: &Position) -> Option<f32> {
+    if pos.x < 0 || pos.y < 0 || pos.z < 0 {
+        return None;
+    }
+    data[0].get(pos.z as usize)
+        .and_then(|slice| slice.get(pos.y as usize))
+        .and_then(|row| row.get(pos.x as usize))
+        .cloned()
+}
+
+//Return the hemisphere of a voxel from the hemisphere mask or the white matter label, 0 if it is unknown.
+//Commissural voxels (corpus callosum) are not assigned to a hemisphere.
+fn voxel_hemisphere(label: f32, mask: Option<f32>) -> i32 {
+    if labels::is_callosum(label) {
+        return 0;
+    }
+    match mask {
+        Some(m) if m as i32 == labels::LEFT_HEMISPHERE || m as i32 == labels::RIGHT_HEMISPHERE => m as i32,
+        Some(_) => 0,
+        None if label == labels::LH_WM_LABEL => labels::LEFT_HEMISPHERE,
+        None if label == labels::RH_WM_LABEL => labels::RIGHT_HEMISPHERE,
+        None => 0,
+    }
+}
+
+//Return the shard of a voxel
+fn shard(pos: &Position, shards: usize) -> usize {
+    pos.z.rem_euclid(shards as i32) as usize
+}
+
+//Return the label histogram of a voxel
+fn get_histogram<'a>(histograms: &'a Histograms, pos: &Position) -> Option<&'a LabelHistogram> {
+    histograms[shard(pos, histograms.len())].get(pos)
+}
+
+//Choose the label of a voxel from its histogram and the histograms of the neighbouring voxels, 0 if there is none.
+//Labels with the same probability are chosen by the tie break rule, the result does not depend on the order of the labels.
+fn choose_label(pos: &Position, histogram: &LabelHistogram, histograms: &Histograms,
+                offsets: &[([i32; 3], f32)], tie_break: TieBreak) -> i32 {
+    let mut highest_prob = 0f32;
+    let mut highest: Option<&LabelCount> = None;
+
+    //Every label is evaluated once
+    for count in histogram.counts.iter() {
+        let label = count.label;
+
+        //Compute probability for the current voxel
+        let curr_prob = histogram.prob(label);
+
+        //Compute commulative probability for neighbouring voxels
+        let mut neigh_prob = 0f32;
+        let mut neigh_count = 0i32;
+        for (offset, weight) in offsets.iter() {
+            if let Some(h) = get_histogram(histograms, &Position{x: pos.x + offset[0], y: pos.y + offset[1], z: pos.z + offset[2]}) {
+                let np = h.prob(label);
+                if np > 0.0 {
+                    neigh_prob += weight * np;
+                    neigh_count += 1
+                }
+            }
+        }
+        //Norm neighbour probability
+        if neigh_count == 0 {
+            neigh_prob = 0.0;
+        } else {
+            neigh_prob /= neigh_count as f32;
+        }
+
+        //Add the probabilities up, compare to highest label, and choose new highest label
+        let prob = curr_prob + neigh_prob;
+        let better = match highest {
+            None => prob > 0.0,
+            Some(_) if prob != highest_prob => prob > highest_prob,
+            Some(h) => match tie_break {
+                TieBreak::Lowest => label < h.label,
+                TieBreak::Votes => (count.weight, -label) > (h.weight, -h.label),
+                TieBreak::Endpoint => count.distance < h.distance || (count.distance == h.distance && label < h.label),
+            },
+        };
+        if better {
+            highest_prob = prob;
+            highest = Some(count);
+        }
+    }
+    highest.map_or(0, |h| h.label)
+}
+
+fn rel_dist(x: f32, y: f32, z: f32) -> f32 {
+    (x * x + y * y + z * z).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nifti;
+
+    //Header of an image with 1 mm voxels
+    fn test_header(dim: [u16; 3]) -> NIfTI1Header {
+        let identity = [[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]];
+        nifti::header([dim[0], dim[1], dim[2], 1], &identity)
+    }
+
+    //Volume of a single row of voxels along x
+    fn row(labels: &[f32]) -> (NIfTI1Header, NIfTI1Data) {
+        (test_header([labels.len() as u16, 1, 1]), vec![vec![vec![labels.to_vec()]]])
+    }
+
+    //Streamline through the voxels from..to (inclusive) of a row, in the given order
+    fn line(from: i32, to: i32) -> Streamline {
+        if from <= to {
+            (from..=to).map(|x| [x as f32, 0.0, 0.0]).collect()
+        } else {
+            (to..=from).rev().map(|x| [x as f32, 0.0, 0.0]).collect()
+        }
+    }
+
+    //Segmentation with cortex at both x faces (1001-1003 left, 2001-2003 right), the white matter of
+    //both hemispheres in between and a strip of corpus callosum
+    fn brain() -> (NIfTI1Header, NIfTI1Data) {
+        let (nx, ny, nz) = (10, 8, 6);
+        let mut ndata: NIfTI1Data = vec![vec![vec![vec![0.0; nx]; ny]; nz]];
+        for (z, slice) in ndata[0].iter_mut().enumerate() {
+            for (y, row) in slice.iter_mut().enumerate() {
+                for (x, voxel) in row.iter_mut().enumerate() {
+                    *voxel = match x {
+                        0 => 1001.0 + (y % 3) as f32,
+                        9 => 2001.0 + (y % 3) as f32,
+                        4 | 5 if z == 0 => 251.0,
+                        1..=4 => 2.0,
+                        _ => 41.0,
+                    };
+                }
+            }
+        }
+        (test_header([nx as u16, ny as u16, nz as u16]), ndata)
+    }
+
+    //Straight tracts between pseudo random points of the brain() volume, with pseudo random weights
+    fn random_tracts(n: usize) -> (Vec<Streamline>, Vec<f32>) {
+        let mut state = 12345u64;
+        let mut next = |max: f32| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 40) as f32 / (1u64 << 24) as f32 * max
+        };
+        let mut tracts = Vec::with_capacity(n);
+        let mut weights = Vec::with_capacity(n);
+        for _ in 0..n {
+            let from = [next(9.0), next(7.0), next(5.0)];
+            let to = [next(9.0), next(7.0), next(5.0)];
+            tracts.push((0..12).map(|i| {
+                let t = i as f32 / 11.0;
+                [from[0] + t * (to[0] - from[0]), from[1] + t * (to[1] - from[1]), from[2] + t * (to[2] - from[2])]
+            }).collect());
+            weights.push(0.1 + next(2.0));
+        }
+        (tracts, weights)
+    }
+
+    //Assert that two results have the same labels and statistics
+    fn assert_same(a: &ParcellationResult, b: &ParcellationResult) {
+        assert!(a.volume == b.volume);
+        assert!(a.labels == b.labels);
+        assert!(a.labelled == b.labelled);
+        let (a, b) = (&a.stats, &b.stats);
+        assert_eq!((a.tracts, a.kept_tracts, a.labelled_tracts, a.votes, a.rejected_votes),
+                   (b.tracts, b.kept_tracts, b.labelled_tracts, b.votes, b.rejected_votes));
+        assert_eq!((a.voted_voxels, a.non_wm_voxels, a.depth_voxels, a.labelled_voxels),
+                   (b.voted_voxels, b.non_wm_voxels, b.depth_voxels, b.labelled_voxels));
+    }
+
+    //Labels of the first frame of a row
+    fn labels_of(result: &ParcellationResult) -> Vec<f32> {
+        result.volume[0][0][0].clone()
+    }
+
+    #[test]
+    fn wm_offset_renumbers_into_the_freesurfer_range() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 41.0, 41.0, 2001.0]);
+        let tracts = vec![line(0, 2), line(5, 3)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1001.0, 1001.0, 2001.0, 2001.0, 0.0]);
+
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).wm_offset(true).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 3001.0, 3001.0, 4001.0, 4001.0, 0.0]);
+        assert_eq!(result.tract_labels, vec![1001, 2001]);
+    }
+
+    #[test]
+    fn wm_offset_follows_the_cortex_labels() {
+        let (nheader, ndata) = row(&[11101.0, 2.0, 2.0, 0.0]);
+        let destrieux = CortexLabels::new(&[11101], &[12101]);
+        let result = Parcellation::new().kernel(Kernel::None).cortex_labels(destrieux).wm_offset(true)
+            .run(&nheader, &ndata, &[line(0, 2)], None);
+        assert_eq!(labels_of(&result), vec![0.0, 13101.0, 13101.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "overlap the cortex labels")]
+    fn wm_offset_rejects_overlapping_cortex_labels() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 3001.0]);
+        Parcellation::new().cortex_labels(CortexLabels::new(&[1001], &[3001])).merge(true)
+            .run(&nheader, &ndata, &[line(0, 1)], None);
+    }
+
+    #[test]
+    fn merge_keeps_the_input_labels() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 2.0, 41.0, 17.0, 2001.0]);
+        let tracts = vec![line(0, 2)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).merge(true).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![1001.0, 3001.0, 3001.0, 2.0, 41.0, 17.0, 2001.0]);
+        assert_eq!(result.labelled.len(), 2);
+    }
+
+    #[test]
+    fn labelwm_fills_the_unsegmented_white_matter() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 2.0, 41.0, 17.0, 2001.0]);
+        let tracts = vec![line(0, 2)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).labelwm(true).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![1001.0, 3001.0, 3001.0, 5001.0, 5002.0, 17.0, 2001.0]);
+    }
+
+    #[test]
+    fn hemisphere_rejects_votes_of_the_other_hemisphere() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 251.0, 41.0, 41.0, 2001.0]);
+        let tracts = vec![line(0, 4)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1001.0, 1001.0, 1001.0, 1001.0, 0.0]);
+        assert_eq!(result.stats.rejected_votes, 0);
+
+        //The corpus callosum belongs to no hemisphere
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).hemisphere(true).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1001.0, 1001.0, 0.0, 0.0, 0.0]);
+        assert_eq!(result.stats.votes, 5);
+        assert_eq!(result.stats.rejected_votes, 2);
+
+        //The mask overrides the white matter label
+        let mask = vec![vec![vec![vec![1.0, 1.0, 1.0, 1.0, 2.0, 2.0]]]];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).hemisphere_mask(mask).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1001.0, 1001.0, 1001.0, 0.0, 0.0]);
+        assert_eq!(result.stats.rejected_votes, 1);
+    }
+
+    #[test]
+    fn weights_change_the_winner() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 2.0, 1002.0]);
+        let tracts = vec![line(0, 3), line(0, 3), line(4, 1)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1001.0, 1001.0, 1001.0, 0.0]);
+
+        let weights = [0.5, 0.5, 3.0];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).run(&nheader, &ndata, &tracts, Some(&weights));
+        assert_eq!(labels_of(&result), vec![0.0, 1002.0, 1002.0, 1002.0, 0.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected 3 weights, but got 2 weights")]
+    fn weights_must_match_the_tracts() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 2.0, 1002.0]);
+        let tracts = vec![line(0, 3), line(0, 3), line(4, 1)];
+        Parcellation::new().run(&nheader, &ndata, &tracts, Some(&[1.0, 1.0]));
+    }
+
+    #[test]
+    fn neighbours_add_their_votes() {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 2.0, 1002.0]);
+        let tracts = vec![vec![[0.0, 0.0, 0.0], [2.0, 0.0, 0.0]], line(4, 1)];
+        let result = Parcellation::new().kernel(Kernel::None).threads(1).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1002.0, 1001.0, 1002.0, 0.0]);
+
+        let result = Parcellation::new().kernel(Kernel::Octant).threads(1).run(&nheader, &ndata, &tracts, None);
+        assert_eq!(labels_of(&result), vec![0.0, 1002.0, 1002.0, 1002.0, 0.0]);
+        assert_eq!(result.labels.get(&Position{x: 0, y: 0, z: 0}), Some(&1001));
+        assert_eq!(result.stats.votes, 6);
+        assert_eq!(result.stats.voted_voxels, 5);
+        assert_eq!(result.stats.non_wm_voxels, 2);
+    }
+
+    #[test]
+    fn threads_do_not_change_the_result() {
+        let (nheader, ndata) = brain();
+        let (tracts, weights) = random_tracts(2 * TRACT_CHUNK + 500);
+        let single = Parcellation::new().kernel(Kernel::Full).hemisphere(true).threads(1).run(&nheader, &ndata, &tracts, Some(&weights));
+        let multi = Parcellation::new().kernel(Kernel::Full).hemisphere(true).threads(4).run(&nheader, &ndata, &tracts, Some(&weights));
+        assert!(single.stats.labelled_voxels > 0 && single.stats.rejected_votes > 0);
+        assert_same(&single, &multi);
+        assert_eq!(single.tract_labels, multi.tract_labels);
+    }
+
+    #[test]
+    fn tract_order_does_not_change_the_result() {
+        let (nheader, ndata) = brain();
+        let (tracts, weights) = random_tracts(3000);
+        let forward = Parcellation::new().kernel(Kernel::Full).threads(1).run(&nheader, &ndata, &tracts, Some(&weights));
+
+        let reversed: Vec<Streamline> = tracts.iter().rev().cloned().collect();
+        let reversed_weights: Vec<f32> = weights.iter().rev().cloned().collect();
+        let backward = Parcellation::new().kernel(Kernel::Full).threads(1).run(&nheader, &ndata, &reversed, Some(&reversed_weights));
+        assert_same(&forward, &backward);
+
+        //Interleave the tracts of both halves
+        let order: Vec<usize> = (0..tracts.len()).map(|i| if i % 2 == 0 { i / 2 } else { tracts.len() - 1 - i / 2 }).collect();
+        let shuffled: Vec<Streamline> = order.iter().map(|i| tracts[*i].clone()).collect();
+        let shuffled_weights: Vec<f32> = order.iter().map(|i| weights[*i]).collect();
+        let shuffled = Parcellation::new().kernel(Kernel::Full).threads(3).run(&nheader, &ndata, &shuffled, Some(&shuffled_weights));
+        assert_same(&forward, &shuffled);
+    }
+
+    //Label of voxel 2 of the row 1001, 2, 2, 1002 whose two labels are tied: the votes of voxel 1
+    //(1001 weights[0], 1002 weights[2]) add to the votes of voxel 2 (1001 weights[1], 1002 weights[3])
+    fn tied_label(tie_break: TieBreak, weights: [f32; 4]) -> f32 {
+        let (nheader, ndata) = row(&[1001.0, 2.0, 2.0, 1002.0]);
+        let tracts = vec![vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0]], vec![[0.0, 0.0, 0.0], [2.0, 0.0, 0.0]],
+                          vec![[3.0, 0.0, 0.0], [1.0, 0.0, 0.0]], vec![[3.0, 0.0, 0.0], [2.0, 0.0, 0.0]]];
+        let result = Parcellation::new().kernel(Kernel::Octant).tie_break(tie_break).threads(1).run(&nheader, &ndata, &tracts, Some(&weights));
+        labels_of(&result)[2]
+    }
+
+    #[test]
+    fn tie_break_lowest_takes_the_lower_label() {
+        assert_eq!(tied_label(TieBreak::Lowest, [3.0, 1.0, 1.0, 3.0]), 1001.0);
+        assert_eq!(tied_label(TieBreak::Lowest, [1.0, 3.0, 3.0, 1.0]), 1001.0);
+    }
+
+    #[test]
+    fn tie_break_votes_takes_the_label_with_most_votes() {
+        assert_eq!(tied_label(TieBreak::Votes, [3.0, 1.0, 1.0, 3.0]), 1002.0);
+        assert_eq!(tied_label(TieBreak::Votes, [1.0, 3.0, 3.0, 1.0]), 1001.0);
+    }
+
+    #[test]
+    fn tie_break_endpoint_takes_the_nearest_endpoint() {
+        assert_eq!(tied_label(TieBreak::Endpoint, [3.0, 1.0, 1.0, 3.0]), 1002.0);
+        assert_eq!(tied_label(TieBreak::Endpoint, [1.0, 3.0, 3.0, 1.0]), 1002.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The depth band has to be 0 <= min <= max")]
+    fn depth_band_rejects_min_above_max() {
+        Parcellation::new().depth_band(5.0, 2.0);
+    }
+}
@@ -0,0 +1,224 @@
+//Along-tract profiles: scalar values (e.g. FA) sampled at equidistant points along the streamlines,
+//averaged over the streamlines of a cortex label or of a pair of cortex labels
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use labels::CortexLabels;
+use lut;
+use nifti::{NIfTI1Data, NIfTI1Header};
+use parcellation::{EndpointRule, ParcellationResult};
+use trackvis;
+use trackvis::{Position, Streamline};
+
+//Count, sum and sum of squares of the values of every scalar volume at every point of a profile
+type ProfileSums = Vec<Vec<(usize, f64, f64)>>;
+
+//Groups of streamlines that are averaged
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Grouping {
+    Label,      //The cortex label of the streamline, the profile starts at the cortex.
+    Pair,       //The first and last cortex label along the streamline, the profile runs from the lower to the higher label.
+}
+
+//Mean profile of a scalar volume over a group of streamlines
+pub struct Profile {
+    pub labels: (i32, i32),     //Cortex label (and 0) or pair of cortex labels.
+    pub scalar: usize,          //Index of the scalar volume.
+    pub streamlines: usize,     //Streamlines of the group.
+    pub samples: Vec<usize>,    //Streamlines with a finite value at every point.
+    pub mean: Vec<f32>,         //Mean at every point, NaN without samples.
+    pub std: Vec<f32>,          //Standard deviation of the population at every point.
+}
+
+//Return the path of a streamline (in voxel coordinates) resampled to the given number of points, equidistant in mm
+pub fn resample(path: &[[f32; 3]], points: usize, spacing: [f32; 3]) -> Vec<[f32; 3]> {
+    if path.is_empty() || points == 0 {
+        return Vec::new();
+    }
+    if path.len() == 1 {
+        return vec![path[0]; points];
+    }
+
+    //Distance of every point to the start
+    let mut distances = vec![0.0f32; path.len()];
+    for i in 1..path.len() {
+        let step: f32 = (0..3).map(|c| ((path[i][c] - path[i - 1][c]) * spacing[c]).powi(2)).sum();
+        distances[i] = distances[i - 1] + step.sqrt();
+    }
+    let length = distances[path.len() - 1];
+
+    let mut segment = 0;
+    (0..points).map(|i| {
+        let distance = if points > 1 { length * i as f32 / (points - 1) as f32 } else { 0.0 };
+        while segment + 2 < path.len() && distances[segment + 1] < distance {
+            segment += 1;
+        }
+        let span = distances[segment + 1] - distances[segment];
+        let t = if span > 0.0 { ((distance - distances[segment]) / span).clamp(0.0, 1.0) } else { 0.0 };
+        let (a, b) = (path[segment], path[segment + 1]);
+        [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t, a[2] + (b[2] - a[2]) * t]
+    }).collect()
+}
+
+//Return the value of the first frame at a position in voxel coordinates, interpolated trilinearly.
+//NaN outside of the voxel centers of the volume.
+pub fn trilinear(data: &NIfTI1Data, point: [f32; 3]) -> f32 {
+    let size = [data[0][0][0].len(), data[0][0].len(), data[0].len()];
+    let mut lower = [0usize; 3];
+    let mut upper = [0usize; 3];
+    let mut t = [0.0f32; 3];
+    for c in 0..3 {
+        if !(point[c] >= 0.0 && point[c] <= (size[c] - 1) as f32) {
+            return f32::NAN;
+        }
+        lower[c] = point[c].floor() as usize;
+        upper[c] = (lower[c] + 1).min(size[c] - 1);
+        t[c] = point[c] - lower[c] as f32;
+    }
+
+    let mut value = 0.0;
+    for corner in 0..8 {
+        let (x, wx) = if corner & 1 == 0 { (lower[0], 1.0 - t[0]) } else { (upper[0], t[0]) };
+        let (y, wy) = if corner & 2 == 0 { (lower[1], 1.0 - t[1]) } else { (upper[1], t[1]) };
+        let (z, wz) = if corner & 4 == 0 { (lower[2], 1.0 - t[2]) } else { (upper[2], t[2]) };
+        let weight = wx * wy * wz;
+        if weight > 0.0 {
+            value += weight * data[0][z][y][x];
+        }
+    }
+    value
+}
+
+//Return the first and last cortex label along a fiber, None if it does not reach the cortex
+fn cortex_pair(ndata: &NIfTI1Data, cortex_labels: &CortexLabels, fiber: &[Position]) -> Option<(i32, i32)> {
+    let cortex = |pos: &Position| -> Option<i32> {
+        if pos.x < 0 || pos.y < 0 || pos.z < 0 {
+            return None;
+        }
+        let label = *ndata[0].get(pos.z as usize)?.get(pos.y as usize)?.get(pos.x as usize)?;
+        if cortex_labels.contains(label) { Some(label as i32) } else { None }
+    };
+    let first = fiber.iter().find_map(cortex)?;
+    let last = fiber.iter().rev().find_map(cortex)?;
+    Some((first, last))
+}
+
+//Return the mean profile of every scalar volume for every group of kept and labelled streamlines, sorted by labels and scalar.
+//The segmentation and scalar volumes share the grid, cortex_labels and endpoint_rule are those of the parcellation
+//so the profiles of a label start at the cortex endpoint that gave the label.
+#[allow(clippy::too_many_arguments)]
+pub fn profiles(nheader: &NIfTI1Header, ndata: &NIfTI1Data, result: &ParcellationResult, tracts: &[Streamline], scalars: &[NIfTI1Data],
+                points: usize, grouping: Grouping, cortex_labels: &CortexLabels, endpoint_rule: EndpointRule) -> Vec<Profile> {
+    let spacing = [nheader.pixdim[1].abs(), nheader.pixdim[2].abs(), nheader.pixdim[3].abs()];
+
+    //Sums and sums of squares of every group and scalar at every point
+    let mut sums: BTreeMap<(i32, i32), (usize, ProfileSums)> = BTreeMap::new();
+    for ((tract, label), kept) in tracts.iter().zip(result.tract_labels.iter()).zip(result.keep.iter()) {
+        if !*kept || *label <= 0 {
+            continue;
+        }
+        let (key, reverse) = match grouping {
+            Grouping::Label => ((*label, 0), endpoint_rule == EndpointRule::Last),
+            Grouping::Pair => match cortex_pair(ndata, cortex_labels, &trackvis::fiber(tract)) {
+                Some((first, last)) => ((first.min(last), first.max(last)), first > last),
+                None => continue,
+            },
+        };
+
+        let mut path = resample(tract, points, spacing);
+        if reverse {
+            path.reverse();
+        }
+        let group = sums.entry(key).or_insert_with(|| (0, vec![vec![(0, 0.0, 0.0); points]; scalars.len()]));
+        group.0 += 1;
+        for (scalar_sums, scalar) in group.1.iter_mut().zip(scalars.iter()) {
+            for (sum, point) in scalar_sums.iter_mut().zip(path.iter()) {
+                let value = trilinear(scalar, *point);
+                if value.is_finite() {
+                    sum.0 += 1;
+                    sum.1 += value as f64;
+                    sum.2 += (value as f64).powi(2);
+                }
+            }
+        }
+    }
+
+    let mut table = Vec::new();
+    for (labels, (streamlines, scalar_sums)) in sums.into_iter() {
+        for (scalar, point_sums) in scalar_sums.into_iter().enumerate() {
+            let mut profile = Profile {
+                labels,
+                scalar,
+                streamlines,
+                samples: Vec::with_capacity(points),
+                mean: Vec::with_capacity(points),
+                std: Vec::with_capacity(points),
+            };
+            for (n, sum, squares) in point_sums.into_iter() {
+                let mean = sum / n as f64;
+                let variance = if n > 0 { (squares / n as f64 - mean * mean).max(0.0) } else { f64::NAN };
+                profile.samples.push(n);
+                profile.mean.push(mean as f32);
+                profile.std.push(variance.sqrt() as f32);
+            }
+            table.push(profile);
+        }
+    }
+    table
+}
+
+//Write the profiles as comma separated table with one row per group, scalar volume and point,
+//scalar_names are the names of the scalar volumes (e.g. fa)
+pub fn write_profiles(table: &[Profile], scalar_names: &[String], grouping: Grouping, file_name: &str) {
+    let mut text = match grouping {
+        Grouping::Label => String::from("label,name"),
+        Grouping::Pair => String::from("label_a,name_a,label_b,name_b"),
+    };
+    text.push_str(",scalar,streamlines,point,samples,mean,std\n");
+
+    for profile in table.iter() {
+        let group = match grouping {
+            Grouping::Label => format!("{},{}", profile.labels.0, lut::lookup(profile.labels.0).0),
+            Grouping::Pair => format!("{},{},{},{}", profile.labels.0, lut::lookup(profile.labels.0).0,
+                                      profile.labels.1, lut::lookup(profile.labels.1).0),
+        };
+        for point in 0..profile.mean.len() {
+            text.push_str(&format!("{},{},{},{},{},{:.6},{:.6}\n", group, scalar_names[profile.scalar], profile.streamlines,
+                                   point, profile.samples[point], profile.mean[point], profile.std[point]));
+        }
+    }
+
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+    if let Err(why) = file.write_all(text.as_bytes()) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resample_is_equidistant_in_mm() {
+        //2 mm along x, then 4 mm along y
+        let path = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 2.0, 0.0]];
+        let points = resample(&path, 4, [2.0, 2.0, 1.0]);
+        assert_eq!(points, vec![[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [1.0, 1.0, 0.0], [1.0, 2.0, 0.0]]);
+        assert_eq!(resample(&path[..1], 2, [2.0, 2.0, 1.0]), vec![[0.0, 0.0, 0.0]; 2]);
+    }
+
+    #[test]
+    fn trilinear_interpolates_between_voxel_centers() {
+        let data = vec![vec![vec![vec![0.0, 1.0], vec![2.0, 3.0]]]];
+        assert_eq!(trilinear(&data, [0.5, 0.5, 0.0]), 1.5);
+        assert_eq!(trilinear(&data, [1.0, 0.0, 0.0]), 1.0);
+        assert!(trilinear(&data, [1.5, 0.0, 0.0]).is_nan());
+    }
+}
@@ -0,0 +1,252 @@
+//Statistics of label volumes and of the white matter parcels of a parcellation
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use filter;
+use lut;
+use nifti;
+use nifti::{NIfTI1Data, NIfTI1Header};
+use parcellation::ParcellationResult;
+use trackvis::Streamline;
+
+//Return the number of voxels of every label (except 0) in the first frame
+pub fn label_voxels(data: &NIfTI1Data) -> BTreeMap<i32, usize> {
+    let mut counts = BTreeMap::new();
+    for value in data[0].iter().flatten().flatten() {
+        let label = *value as i32;
+        if label != 0 {
+            *counts.entry(label).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+//White matter parcel and tracts of a cortex label
+pub struct LabelStats {
+    pub label: i32,             //Cortex label.
+    pub voxels: usize,          //White matter voxels labelled.
+    pub volume: f32,            //Volume of these voxels in mm^3.
+    pub streamlines: usize,     //Kept streamlines labelled by this cortex region.
+    pub mean_length: f32,       //Mean length of these streamlines in mm, NaN without streamlines.
+    pub scalar_means: Vec<f32>, //Mean of the finite values of every scalar volume within the parcel, NaN without such voxels.
+}
+
+//Return the statistics of every cortex label with white matter voxels or streamlines, sorted by label.
+//The tracts are given in voxel coordinates, the scalar volumes (e.g. FA, MD) have to be in the grid of the segmentation,
+//voxels with NaN or infinite values are ignored like in scalar_stats.
+pub fn label_stats(nheader: &NIfTI1Header, result: &ParcellationResult, tracts: &[Streamline], scalars: &[NIfTI1Data]) -> Vec<LabelStats> {
+    let spacing = [nheader.pixdim[1].abs(), nheader.pixdim[2].abs(), nheader.pixdim[3].abs()];
+    let voxel_volume = nifti::voxel_volume(nheader);
+
+    //Statistics of every label together with the number of finite values and their sum for every scalar volume
+    let mut table: BTreeMap<i32, (LabelStats, Vec<(usize, f64)>)> = BTreeMap::new();
+    let entry = |label: i32| (LabelStats {
+        label,
+        voxels: 0,
+        volume: 0.0,
+        streamlines: 0,
+        mean_length: 0.0,
+        scalar_means: Vec::with_capacity(scalars.len()),
+    }, vec![(0, 0.0); scalars.len()]);
+
+    //Sums of the voxels of the parcels
+    for pos in result.labelled.iter() {
+        let label = result.labels[pos];
+        let (stats, sums) = table.entry(label).or_insert_with(|| entry(label));
+        stats.voxels += 1;
+        for (sum, scalar) in sums.iter_mut().zip(scalars.iter()) {
+            let value = scalar[0][pos.z as usize][pos.y as usize][pos.x as usize];
+            if value.is_finite() {
+                sum.0 += 1;
+                sum.1 += value as f64;
+            }
+        }
+    }
+
+    //Sums of the streamlines
+    for ((tract, label), kept) in tracts.iter().zip(result.tract_labels.iter()).zip(result.keep.iter()) {
+        if *kept && *label > 0 {
+            let (stats, _) = table.entry(*label).or_insert_with(|| entry(*label));
+            stats.streamlines += 1;
+            stats.mean_length += filter::length(tract, spacing);
+        }
+    }
+
+    table.into_values().map(|(mut stats, sums)| {
+        stats.volume = stats.voxels as f32 * voxel_volume;
+        stats.mean_length = if stats.streamlines > 0 { stats.mean_length / stats.streamlines as f32 } else { f32::NAN };
+        stats.scalar_means = sums.into_iter().map(|(n, sum)| if n > 0 { (sum / n as f64) as f32 } else { f32::NAN }).collect();
+        stats
+    }).collect()
+}
+
+//Write the statistics as tab separated table, scalar_names are the names of the scalar volumes (e.g. fa)
+pub fn write_label_stats(table: &[LabelStats], scalar_names: &[String], file_name: &str) {
+    let mut text = String::from("label\tname\twm_voxels\twm_volume_mm3\tstreamlines\tmean_length_mm");
+    for name in scalar_names.iter() {
+        text.push_str(&format!("\tmean_{}", name));
+    }
+    text.push('\n');
+
+    for stats in table.iter() {
+        text.push_str(&format!("{}\t{}\t{}\t{:.2}\t{}\t{:.2}", stats.label, lut::lookup(stats.label).0, stats.voxels,
+                               stats.volume, stats.streamlines, stats.mean_length));
+        for mean in stats.scalar_means.iter() {
+            text.push_str(&format!("\t{:.6}", mean));
+        }
+        text.push('\n');
+    }
+    write_text(&text, file_name);
+}
+
+//Distribution of a scalar volume within the white matter parcel of a cortex label
+pub struct ScalarStats {
+    pub label: i32,   //Cortex label.
+    pub scalar: usize, //Index of the scalar volume.
+    pub voxels: usize, //Voxels of the parcel with a finite value.
+    pub mean: f32,
+    pub median: f32,
+    pub std: f32,     //Standard deviation of the population.
+    pub p5: f32,
+    pub p25: f32,
+    pub p75: f32,
+    pub p95: f32,
+}
+
+//Return the percentile p (0-100) of sorted values, interpolated linearly between the nearest ranks
+fn percentile(sorted: &[f32], p: f32) -> f32 {
+    if sorted.is_empty() {
+        return f32::NAN;
+    }
+    let rank = p / 100.0 * (sorted.len() - 1) as f32;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f32)
+}
+
+//Return the mean and the standard deviation of the population of values, NaN without values
+fn mean_std(values: &[f32]) -> (f32, f32) {
+    let n = values.len() as f64;
+    let mean = values.iter().map(|v| *v as f64).sum::<f64>() / n;
+    let variance = values.iter().map(|v| (*v as f64 - mean).powi(2)).sum::<f64>() / n;
+    (mean as f32, variance.sqrt() as f32)
+}
+
+//Return the statistics of every scalar volume within every white matter parcel, sorted by label and scalar.
+//The scalar volumes have to be in the grid of the segmentation, voxels with NaN or infinite values are ignored.
+pub fn scalar_stats(result: &ParcellationResult, scalars: &[NIfTI1Data]) -> Vec<ScalarStats> {
+    //Values of every scalar per label
+    let mut values: BTreeMap<i32, Vec<Vec<f32>>> = BTreeMap::new();
+    for pos in result.labelled.iter() {
+        let samples = values.entry(result.labels[pos]).or_insert_with(|| vec![Vec::new(); scalars.len()]);
+        for (sample, scalar) in samples.iter_mut().zip(scalars.iter()) {
+            let value = scalar[0][pos.z as usize][pos.y as usize][pos.x as usize];
+            if value.is_finite() {
+                sample.push(value);
+            }
+        }
+    }
+
+    let mut table = Vec::new();
+    for (label, samples) in values.into_iter() {
+        for (scalar, mut sample) in samples.into_iter().enumerate() {
+            sample.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            let (mean, std) = mean_std(&sample);
+            table.push(ScalarStats {
+                label,
+                scalar,
+                voxels: sample.len(),
+                mean,
+                median: percentile(&sample, 50.0),
+                std,
+                p5: percentile(&sample, 5.0),
+                p25: percentile(&sample, 25.0),
+                p75: percentile(&sample, 75.0),
+                p95: percentile(&sample, 95.0),
+            });
+        }
+    }
+    table
+}
+
+//Write the statistics as tab separated table with one row per label and scalar volume,
+//scalar_names are the names of the scalar volumes (e.g. fa)
+pub fn write_scalar_stats(table: &[ScalarStats], scalar_names: &[String], file_name: &str) {
+    let mut text = String::from("label\tname\tscalar\tvoxels\tmean\tmedian\tstd\tp5\tp25\tp75\tp95\n");
+    for stats in table.iter() {
+        text.push_str(&format!("{}\t{}\t{}\t{}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\t{:.6}\n", stats.label,
+                               lut::lookup(stats.label).0, scalar_names[stats.scalar], stats.voxels, stats.mean,
+                               stats.median, stats.std, stats.p5, stats.p25, stats.p75, stats.p95));
+    }
+    write_text(&text, file_name);
+}
+
+fn write_text(text: &str, file_name: &str) {
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+    if let Err(why) = file.write_all(text.as_bytes()) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parcellation::Stats;
+    use trackvis::Position;
+
+    #[test]
+    fn label_stats_ignore_non_finite_values() {
+        let positions = [Position{x: 0, y: 0, z: 0}, Position{x: 1, y: 0, z: 0}, Position{x: 2, y: 0, z: 0}];
+        let result = ParcellationResult {
+            volume: vec![vec![vec![vec![1003.0; 3]]]],
+            labels: positions.iter().map(|p| (*p, 1003)).collect(),
+            labelled: positions.to_vec(),
+            tract_labels: Vec::new(),
+            keep: Vec::new(),
+            depth: None,
+            stats: Stats {
+                tracts: 0, kept_tracts: 0, labelled_tracts: 0, votes: 0, rejected_votes: 0, voted_voxels: 3,
+                non_wm_voxels: 0, depth_voxels: 0, labelled_voxels: 3, filter: None,
+            },
+        };
+        let scalars = vec![vec![vec![vec![vec![1.0, f32::NAN, 3.0]]]], vec![vec![vec![vec![f32::INFINITY, f32::NAN, f32::NAN]]]]];
+        let nheader = nifti::header([3, 1, 1, 1], &[[1.0, 0.0, 0.0, 0.0], [0.0, 1.0, 0.0, 0.0], [0.0, 0.0, 1.0, 0.0], [0.0, 0.0, 0.0, 1.0]]);
+
+        let table = label_stats(&nheader, &result, &[], &scalars);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table[0].voxels, 3);
+        assert_eq!(table[0].scalar_means[0], 2.0);
+        assert!(table[0].scalar_means[1].is_nan());
+        assert_eq!(scalar_stats(&result, &scalars)[0].mean, 2.0);
+    }
+
+    #[test]
+    fn percentile_interpolates_between_ranks() {
+        let sorted = [1.0, 2.0, 4.0, 8.0, 16.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 4.0);
+        assert_eq!(percentile(&sorted, 100.0), 16.0);
+        //Rank 0.25 * 4 = 1 and 0.6 * 4 = 2.4
+        assert_eq!(percentile(&sorted, 25.0), 2.0);
+        assert!((percentile(&sorted, 60.0) - 5.6).abs() < 1e-5);
+        assert_eq!(percentile(&[3.0], 95.0), 3.0);
+        assert!(percentile(&[], 50.0).is_nan());
+    }
+
+    #[test]
+    fn mean_std_of_the_population() {
+        let (mean, std) = mean_std(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(mean, 5.0);
+        assert_eq!(std, 2.0);
+        assert_eq!(mean_std(&[3.0]), (3.0, 0.0));
+        let (mean, std) = mean_std(&[]);
+        assert!(mean.is_nan() && std.is_nan());
+    }
+}
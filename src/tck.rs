@@ -0,0 +1,235 @@
+//Definitions of the MRtrix tck datatypes
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+
+pub struct TckHeader {
+    pub properties: Vec<(String, String)>,  //All key/value pairs of the text header in file order.
+    pub datatype: String,                   //Data type of the point stream. Float32LE or Float32BE.
+    pub offset: usize,                      //Byte offset of the point stream (from "file: . offset").
+    pub count: usize,                       //Number of tracks stored in this track file. 0 means the number was NOT stored.
+}
+
+//Parse the text header at the beginning of a tck file
+fn parse_header(in_data: &[u8], display: &str) -> TckHeader {
+    let mut header = TckHeader {
+        properties: Vec::new(),
+        datatype: String::new(),
+        offset: 0,
+        count: 0,
+    };
+
+    let mut lines = in_data.split(|b| *b == b'\n');
+
+    //The first line has to be the magic string
+    match lines.next() {
+        Some(l) if String::from_utf8_lossy(l).trim() == "mrtrix tracks" => {},
+        _ => panic!("{} is not a MRtrix track file!", display),
+    }
+
+    let mut found_end = false;
+    for line in lines {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line == "END" {
+            found_end = true;
+            break;
+        }
+
+        let (key, value) = match line.find(':') {
+            Some(i) => (line[..i].trim().to_string(), line[i + 1..].trim().to_string()),
+            None => continue,
+        };
+
+        match key.as_str() {
+            "datatype" => header.datatype = value.clone(),
+            "count" => header.count = value.parse().unwrap_or(0),
+            "file" => {
+                //The data file is given as ". offset" for data stored in the same file
+                let mut parts = value.split_whitespace();
+                if parts.next() != Some(".") {
+                    panic!("Track data of {} is not stored in the same file!", display);
+                }
+                header.offset = match parts.next().map(|o| o.parse()) {
+                    Some(Ok(o)) => o,
+                    _ => panic!("Could not parse the data offset of {}!", display),
+                };
+            },
+            _ => {},
+        }
+        header.properties.push((key, value));
+    }
+
+    if !found_end {
+        panic!("Could not find the end of the header of {}!", display);
+    }
+    if header.datatype != "Float32LE" && header.datatype != "Float32BE" {
+        panic!("Unsupported datatype {} in {}!", header.datatype, display);
+    }
+    if header.offset == 0 || header.offset > in_data.len() {
+        panic!("Wrong data offset in {}!", display);
+    }
+
+    header
+}
+
+//Read a tck file, the points of the streamlines are given in RAS (mm)
+pub fn read(file_name: &str) -> (TckHeader, Vec<Vec<[f32; 3]>>) {
+    let (header, streamlines, terminated) = read_unchecked(file_name);
+
+    if !terminated {
+        eprintln!("Warning: {} is not terminated, the last track might be incomplete.", Path::new(file_name).display());
+    }
+
+    if header.count != 0 && header.count != streamlines.len() {
+        panic!("Something went wrong while reading the tracks. Expected {} tracks, but got {} tracks",
+               header.count, streamlines.len());
+    }
+
+    (header, streamlines)
+}
+
+//Read a tck file without checking the streamlines against count, also return whether the file is terminated
+pub fn read_unchecked(file_name: &str) -> (TckHeader, Vec<Vec<[f32; 3]>>, bool) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    //Read the whole file, the header has no fixed size
+    let mut in_data: Vec<u8> = Vec::new();
+    if let Err(why) = file.read_to_end(&mut in_data) {
+        panic!("Could not read data of {}: {}", display, why);
+    }
+
+    let header = parse_header(&in_data, &display.to_string());
+    let big_endian = header.datatype == "Float32BE";
+
+    let mut streamlines: Vec<Vec<[f32; 3]>> = Vec::new();
+    let mut tmp_streamline: Vec<[f32; 3]> = Vec::new();
+    let mut finished = false;
+
+    //Every point is a triplet of floats. A NaN triplet ends a track, an Inf triplet ends the file.
+    for point in in_data[header.offset..].chunks(12) {
+        if point.len() < 12 {
+            break;
+        }
+
+        let mut xyz = [0.0f32; 3];
+        for (n, coord) in xyz.iter_mut().enumerate() {
+            let bytes = [point[4 * n], point[4 * n + 1], point[4 * n + 2], point[4 * n + 3]];
+            *coord = if big_endian { f32::from_be_bytes(bytes) } else { f32::from_le_bytes(bytes) };
+        }
+
+        if xyz[0].is_infinite() {
+            finished = true;
+            break;
+        }
+        if xyz[0].is_nan() {
+            streamlines.push(tmp_streamline);
+            tmp_streamline = Vec::new();
+            continue;
+        }
+
+        tmp_streamline.push(xyz);
+    }
+
+    if !finished && !tmp_streamline.is_empty() {
+        streamlines.push(tmp_streamline);
+    }
+
+    (header, streamlines, finished)
+}
+
+//Write streamlines given in RAS (mm) to a tck file
+pub fn write(streamlines: &[Vec<[f32; 3]>], file_name: &str) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    //The header contains its own size, so grow the offset until it fits
+    let mut offset = 0;
+    let out_header = loop {
+        let tmp = format!("mrtrix tracks\ndatatype: Float32LE\ncount: {}\nfile: . {}\nEND\n",
+                          streamlines.len(), offset);
+        if tmp.len() == offset {
+            break tmp;
+        }
+        offset = tmp.len();
+    };
+
+    if let Err(why) = file.write_all(out_header.as_bytes()) {
+        panic!("Could not write header of {}: {}", display, why);
+    }
+
+    //Write every streamline followed by a NaN triplet and terminate the file with an Inf triplet
+    let mut out_data: Vec<u8> = Vec::new();
+    for streamline in streamlines.iter() {
+        for point in streamline.iter() {
+            for coord in point.iter() {
+                out_data.extend_from_slice(&coord.to_le_bytes());
+            }
+        }
+        for _ in 0..3 {
+            out_data.extend_from_slice(&f32::NAN.to_le_bytes());
+        }
+    }
+    for _ in 0..3 {
+        out_data.extend_from_slice(&f32::INFINITY.to_le_bytes());
+    }
+
+    if let Err(why) = file.write_all(&out_data) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::temp_file;
+
+    #[test]
+    fn write_read_keeps_the_streamlines() {
+        let streamlines = vec![
+            vec![[88.0, -122.0, -66.0], [86.5, -121.25, -65.125]],
+            vec![[82.0, -116.0, -60.0]],
+            vec![[-1.5, 0.0, 2.25], [0.0, 0.0, 0.0], [3.0, 4.0, 5.0]],
+        ];
+        let file = temp_file("roundtrip", "tck");
+        write(&streamlines, &file);
+        let (header, read) = read(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(header.datatype, "Float32LE");
+        assert_eq!(header.count, 3);
+        assert_eq!(read, streamlines);
+        assert_eq!(read.iter().map(|s| s.len()).sum::<usize>(), 6);
+    }
+
+    #[test]
+    fn read_unchecked_reports_a_missing_end() {
+        let streamlines = vec![vec![[1.0, 2.0, 3.0]], vec![[4.0, 5.0, 6.0], [7.0, 8.0, 9.0]]];
+        let file = temp_file("unterminated", "tck");
+        write(&streamlines, &file);
+        //Drop the Inf triplet and the NaN triplet of the last streamline
+        let bytes = fs::read(&file).unwrap();
+        fs::write(&file, &bytes[..bytes.len() - 24]).unwrap();
+        let (header, read, terminated) = read_unchecked(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert!(!terminated);
+        assert_eq!(header.count, 2);
+        assert_eq!(read, streamlines);
+    }
+}
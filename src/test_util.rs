@@ -0,0 +1,17 @@
+//Helpers shared by the tests of the modules
+use std::env;
+use std::process;
+
+use nifti;
+use nifti::NIfTI1Header;
+
+//Return the path of a temporary file, the process id keeps parallel test runs apart
+pub fn temp_file(name: &str, extension: &str) -> String {
+    env::temp_dir().join(format!("wmparc_{}_{}.{}", name, process::id(), extension)).to_string_lossy().into_owned()
+}
+
+//Header of a 12x10x8 image with 2 mm voxels, the x axis flipped (LAS)
+pub fn test_header() -> NIfTI1Header {
+    let affine = [[-2.0, 0.0, 0.0, 90.0], [0.0, 2.0, 0.0, -126.0], [0.0, 0.0, 2.0, -72.0], [0.0, 0.0, 0.0, 1.0]];
+    nifti::header([12, 10, 8, 1], &affine)
+}
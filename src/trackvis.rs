@@ -1,22 +1,24 @@
 //Definitions of the trackvis datatypes
-use std::error::Error;
 use std::mem;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
+use nifti;
 use nifti::NIfTI1Header;
+use tractogram::DataArray;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct TrackVisHeader{
     pub id_string: [u8; 6],                    //0  ID string for track file. The first 5 characters must be "TRACK".
     pub dim: [u16; 3],                         //6   Dimension of the image volume.
     pub voxel_size: [f32; 3],                  //12  Voxel size of the image volume.
     pub origin: [f32; 3],                      //24  Origin of the image volume. This field is not yet being used by TrackVis. That means the origin is always (0, 0, 0).
     pub n_scalars: u16,                        //36  Number of scalars saved at each track point (besides x, y and z coordinates).
-    pub scalar_name: [[u8; 10]; 20],           //38  Name of each scalar. Can not be longer than 20 characters each. Can only store up to 10 names.
+    pub scalar_name: [[u8; 20]; 10],           //38  Name of each scalar. Can not be longer than 20 characters each. Can only store up to 10 names.
     pub n_properties: u16,                     //238 Number of properties saved at each track.
-    pub property_name: [[u8; 10]; 20],         //240 Name of each property. Can not be longer than 20 characters each. Can only store up to 10 names.
+    pub property_name: [[u8; 20]; 10],         //240 Name of each property. Can not be longer than 20 characters each. Can only store up to 10 names.
     pub vox_to_ras: [[f32; 4]; 4],             //440 4x4 matrix for voxel to RAS (crs to xyz) transformation. If vox_to_ras[3][3] is 0, it means the matrix is not recorded. This field is added from version 2.
     pub reserved: [u8; 444],                   //504 Reserved space for future version.
     pub voxel_order: [u8; 4],                  //948 Storing order of the original image data. Explained at http://trackvis.org/docs/ .
@@ -34,7 +36,7 @@ pub struct TrackVisHeader{
     pub hdr_size: u32,                         //996 Size of the header. Used to determine byte swap. Should be 1000.
 }
 
-#[derive(PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[derive(PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -43,127 +45,251 @@ pub struct Position {
 
 pub type Fiber = Vec<Position>;
 
-#[allow(dead_code)]
-pub fn write(nheader: &NIfTI1Header, fibers: &Vec<Fiber>, file_name: &str){
+//Points of a streamline in voxel coordinates of the image, the voxel centers are at integer coordinates
+pub type Streamline = Vec<[f32; 3]>;
 
-    //Open file
-    let path = Path::new(file_name);
-    let display = path.display();
-    let mut file = match File::create(&path){
-        Err(why) => panic!("Could not create {}: {}",
-                           display, Error::description(&why)),
-        Ok(file) => file,
-    };
+//Return the voxel positions of a streamline, every point lies in its nearest voxel
+pub fn fiber(streamline: &[[f32; 3]]) -> Fiber {
+    streamline.iter()
+        .map(|p| Position{x: (p[0] + 0.5).floor() as i32, y: (p[1] + 0.5).floor() as i32, z: (p[2] + 0.5).floor() as i32})
+        .collect()
+}
+
+//A track as stored in the file
+#[derive(Clone)]
+pub struct Track {
+    pub points: Vec<[f32; 3]>,     //Coordinates in mm (voxel size times voxel coordinate, origin at the corner of the first voxel).
+    pub scalars: Vec<f32>,         //n_scalars values per point.
+    pub properties: Vec<f32>,      //n_properties values.
+}
+
+//Return the axis codes of an affine, the letters give the direction every voxel axis points to (e.g. "LAS")
+pub fn orientation_codes(affine: &[[f32; 4]; 4]) -> [u8; 3] {
+    let mut codes = [0u8; 3];
+    for (axis, code) in codes.iter_mut().enumerate() {
+        //The world axis with the largest component determines the code
+        let mut world = 0;
+        for w in 1..3 {
+            if affine[w][axis].abs() > affine[world][axis].abs() {
+                world = w;
+            }
+        }
+        let positive = affine[world][axis] >= 0.0;
+        *code = match (world, positive) {
+            (0, true) => b'R',
+            (0, false) => b'L',
+            (1, true) => b'A',
+            (1, false) => b'P',
+            (2, true) => b'S',
+            _ => b'I',
+        };
+    }
+    codes
+}
+
+//Derive a TrackVis header from the NIfTI image the tracks belong to
+pub fn header_from_nifti(nheader: &NIfTI1Header) -> TrackVisHeader {
+    let affine = nifti::affine(nheader);
 
-    //Fill header
     let mut header: TrackVisHeader = unsafe { mem::zeroed() };
-    header.id_string = ['T' as u8, 'R' as u8, 'A' as u8, 'C' as u8, 'K' as u8, 0 as u8];
+    header.id_string = *b"TRACK\0";
     header.dim = [ nheader.dim[1], nheader.dim[2], nheader.dim[3] ];
-    header.voxel_size = [ nheader.pixdim[1], nheader.pixdim[2], nheader.pixdim[3] ];
-    header.origin = [0.0, 0.0, 0.0];
-    header.n_scalars = 0;
-    header.scalar_name = [[0; 10]; 20];
-    header.n_properties = 0;
-    header.property_name = [[0; 10]; 20];
-    header.vox_to_ras[0] = unsafe{ mem::transmute(nheader.srow_x) };
-    header.vox_to_ras[1] = unsafe{ mem::transmute(nheader.srow_y) };
-    header.vox_to_ras[2] = unsafe{ mem::transmute(nheader.srow_z) };
-    header.vox_to_ras[3] = [0.0, 0.0, 0.0, 1.0];
-    header.reserved = [0u8; 444];
-    header.voxel_order = ['L' as u8, 'A' as u8, 'S' as u8, 0 as u8];
-    header.pad2 = header.voxel_order;
-    header.image_orientation_patient = [1.0, 0.0, 0.0, 0.0, -1.0, 0.0];
-    header.pad1 = [0; 2];
-    header.invert_x = 0;
-    header.invert_y = 0;
-    header.invert_z = 0;
-    header.swap_xy = 0;
-    header.swap_yz = 0;
-    header.swap_zx = 0;
-    header.n_count = fibers.len() as u32;
+    header.voxel_size = [ nheader.pixdim[1].abs(), nheader.pixdim[2].abs(), nheader.pixdim[3].abs() ];
+    header.vox_to_ras = affine;
+
+    let codes = orientation_codes(&affine);
+    header.voxel_order = [codes[0], codes[1], codes[2], 0];
+
+    //Direction cosines of the first two voxel axes in the LPS system of DICOM
+    for (axis, cosines) in header.image_orientation_patient.chunks_mut(3).enumerate() {
+        let column = [affine[0][axis], affine[1][axis], affine[2][axis]];
+        let norm = (column[0] * column[0] + column[1] * column[1] + column[2] * column[2]).sqrt();
+        if norm > 0.0 {
+            cosines[0] = -column[0] / norm;
+            cosines[1] = -column[1] / norm;
+            cosines[2] = column[2] / norm;
+        }
+    }
+
     header.version = 2;
     header.hdr_size = 1000;
+    header
+}
 
-    //Write header
-    let out_header: [u8; 1000] = unsafe {
-        mem::transmute(header)
+//Return the transformation of the track coordinates (mm, origin at the corner of the first voxel) to RAS (mm).
+//Without a stored vox_to_ras the voxel sizes are used.
+pub fn voxmm_to_ras(header: &TrackVisHeader) -> [[f32; 4]; 4] {
+    let vs = header.voxel_size;
+    let vox_to_ras = if header.vox_to_ras[3][3] == 0.0 {
+        [[vs[0], 0.0, 0.0, 0.0], [0.0, vs[1], 0.0, 0.0], [0.0, 0.0, vs[2], 0.0], [0.0, 0.0, 0.0, 1.0]]
+    } else {
+        header.vox_to_ras
     };
-    match file.write_all(&out_header){
-        Err(why) => panic!("Could not write header of {}: {}",
-                           display, Error::description(&why)),
+
+    //Voxel coordinate of a point is mm / voxel size - 0.5, the voxel centers are at integer coordinates
+    let mut m = [[0.0f32; 4]; 4];
+    for (row, ras) in m.iter_mut().zip(vox_to_ras.iter()) {
+        for c in 0..3 {
+            row[c] = ras[c] / vs[c];
+        }
+        row[3] = ras[3] - 0.5 * (ras[0] + ras[1] + ras[2]);
+    }
+    m
+}
+
+//Convert tracks to voxel positions. Every point lies in the voxel that contains its mm coordinate.
+pub fn fibers(header: &TrackVisHeader, tracks: &[Track]) -> Vec<Fiber> {
+    tracks.iter().map(|track| {
+        track.points.iter().map(|p| {
+            //The TrackVis Coordinates are given in mm.
+            //Therefore we convert the TrackVis coordinate to coordinates in the NIfTI image.
+            let x = (p[0] / header.voxel_size[0]).trunc();
+            let y = (p[1] / header.voxel_size[1]).trunc();
+            let z = (p[2] / header.voxel_size[2]).trunc();
+            Position{x: x as i32, y: y as i32, z: z as i32}
+        }).collect()
+    }).collect()
+}
+
+//Convert tracks to voxel coordinates of the image, the mm coordinates are divided by the voxel size
+pub fn streamlines(header: &TrackVisHeader, tracks: &[Track]) -> Vec<Streamline> {
+    let vs = header.voxel_size;
+    tracks.iter().map(|track| {
+        track.points.iter().map(|p| [p[0] / vs[0] - 0.5, p[1] / vs[1] - 0.5, p[2] / vs[2] - 0.5]).collect()
+    }).collect()
+}
+
+//Write streamlines given in RAS (mm) in the space of the image described by nheader together with
+//properties per track (e.g. the cortex label), at most 10 single valued properties.
+pub fn write(nheader: &NIfTI1Header, streamlines: &[Vec<[f32; 3]>], properties: &[DataArray], file_name: &str){
+
+    if properties.len() > 10 || properties.iter().any(|p| p.dim != 1 || p.data.len() != streamlines.len()) {
+        panic!("Wrong properties for {}! Up to 10 properties with one value per track are supported.", file_name);
+    }
+
+    let mut header = header_from_nifti(nheader);
+    header.n_properties = properties.len() as u16;
+    for (name, property) in header.property_name.iter_mut().zip(properties.iter()) {
+        set_name(name, &property.name);
+    }
+
+    let ras_to_voxmm = nifti::inverse_affine(&voxmm_to_ras(&header));
+    let tracks: Vec<Track> = streamlines.iter().enumerate().map(|(s, streamline)| Track {
+        points: streamline.iter().map(|p| nifti::apply_affine(&ras_to_voxmm, *p)).collect(),
+        scalars: Vec::new(),
+        properties: properties.iter().map(|p| p.data[s]).collect(),
+    }).collect();
+
+    write_tracks(&header, &tracks, file_name);
+}
+
+//Set a zero terminated scalar or property name, only 19 characters fit
+pub fn set_name(name: &mut [u8; 20], value: &str) {
+    *name = [0; 20];
+    for (c, b) in name.iter_mut().zip(value.bytes().take(19)) {
+        *c = b;
+    }
+}
+
+//Write tracks with the given header. The header is written as it is, apart from the number of tracks
+//which is always stored.
+pub fn write_tracks(header: &TrackVisHeader, tracks: &[Track], file_name: &str){
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
         Ok(file) => file,
     };
 
-    //Write every entry in data
-    for fiber in fibers.iter(){
+    //Check the tracks against the header
+    let n_scalars = header.n_scalars as usize;
+    let n_properties = header.n_properties as usize;
+    for track in tracks.iter() {
+        if track.scalars.len() != n_scalars * track.points.len() || track.properties.len() != n_properties {
+            panic!("The scalars or properties of a track do not match the header of {}!", display);
+        }
+    }
 
-        //Write length of track
-        let mut tmp: [u8; 4] = unsafe{
-            mem::transmute(fiber.len() as u32)
-        };
-        match file.write_all(&tmp){
-            Err(why) => panic!("Could not read data of {}: {}",
-                               display, Error::description(&why)),
-            Ok(file) => file,
-        };
+    //Write header
+    let mut out_header = *header;
+    out_header.n_count = tracks.len() as u32;
+    let out_header: [u8; 1000] = unsafe {
+        mem::transmute::<TrackVisHeader, [u8; 1000]>(out_header)
+    };
+    if let Err(why) = file.write_all(&out_header) {
+        panic!("Could not write header of {}: {}", display, why);
+    }
 
-        //Write trackpoints
-        //Important note: The coordinates in the track file are in mm, so you have so scale them
-        for point in fiber.iter(){
-            tmp = unsafe{
-                mem::transmute(nheader.pixdim[1] * point.x as f32)
-            };
-            match file.write_all(&tmp){
-                Err(why) => panic!("Could not read data of {}: {}",
-                                   display, Error::description(&why)),
-                Ok(file) => file,
-            };
-            tmp = unsafe{
-                mem::transmute(nheader.pixdim[2] * point.y as f32)
-            };
-            match file.write_all(&tmp){
-                Err(why) => panic!("Could not read data of {}: {}",
-                                   display, Error::description(&why)),
-                Ok(file) => file,
-            };
-            tmp = unsafe{
-                mem::transmute(nheader.pixdim[3] * point.z as f32)
-            };
-            match file.write_all(&tmp){
-                Err(why) => panic!("Could not read data of {}: {}",
-                                   display, Error::description(&why)),
-                Ok(file) => file,
-            };
+    //Write every track: number of points, points with their scalars, properties
+    let mut out_data: Vec<u8> = Vec::new();
+    for track in tracks.iter(){
+        out_data.extend_from_slice(&(track.points.len() as u32).to_le_bytes());
 
+        for (i, point) in track.points.iter().enumerate(){
+            for coord in point.iter() {
+                out_data.extend_from_slice(&coord.to_le_bytes());
+            }
+            for scalar in track.scalars[i * n_scalars..(i + 1) * n_scalars].iter() {
+                out_data.extend_from_slice(&scalar.to_le_bytes());
+            }
         }
+        for property in track.properties.iter() {
+            out_data.extend_from_slice(&property.to_le_bytes());
+        }
+    }
+
+    if let Err(why) = file.write_all(&out_data) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+//Read a track file and convert the tracks to voxel positions
+pub fn read(file_name: &str) -> (TrackVisHeader, Vec<Fiber>) {
+    let (header, tracks) = read_tracks(file_name);
+    let fibers = fibers(&header, &tracks);
+    (header, fibers)
+}
+
+//Read a track file with all points, scalars and properties as stored
+pub fn read_tracks(file_name: &str) -> (TrackVisHeader, Vec<Track>) {
+    let (header, tracks, complete) = read_tracks_unchecked(file_name);
+
+    if !complete {
+        panic!("Unexpected end of data in {}!", Path::new(file_name).display());
+    }
+
+    //Check read data size
+    if header.n_count != 0 && header.n_count as usize != tracks.len() {
+        panic!("Something went wrong while reading the tracks. Expected {} tracks, but got {} tracks",
+               header.n_count, tracks.len());
     }
+
+    (header, tracks)
 }
 
-pub fn read (file_name: &str) -> (TrackVisHeader, Vec<Fiber>) {
+//Read the tracks of a track file without checking them against n_count. A truncated last track
+//is dropped and returned as false, the header and the complete tracks before it are still returned.
+pub fn read_tracks_unchecked(file_name: &str) -> (TrackVisHeader, Vec<Track>, bool) {
 
     //Open file
     let path = Path::new(file_name);
     let display = path.display();
-    let mut file = match File::open(&path){
-        Err(why) => panic!("Could not open {}: {}", display, Error::description(&why)),
+    let mut file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
         Ok(file) => file,
     };
 
     //Read header
-    let mut in_header: [u8; 1000] = unsafe{ mem::zeroed() } ;
-    let header_result = match file.read(&mut in_header){
-        Err(why) => panic!("Could not read header of {}: {}", display, Error::description(&why)),
-        Ok(file) => file,
-    };
-
-    //Check read header size
-    if header_result != (mem::size_of::<TrackVisHeader>()) {
-        panic!("Wrong header size!");
+    let mut in_header: [u8; 1000] = [0; 1000];
+    if let Err(why) = file.read_exact(&mut in_header) {
+        panic!("Could not read header of {}: {}", display, why);
     }
 
-    //Convert read header into NIfTI1Header
+    //Convert read header into TrackVisHeader
     let header: TrackVisHeader = unsafe {
-        mem::transmute(in_header)
+        mem::transmute::<[u8; 1000], TrackVisHeader>(in_header)
     };
 
     //Check integrety
@@ -173,71 +299,142 @@ pub fn read (file_name: &str) -> (TrackVisHeader, Vec<Fiber>) {
 
     //Read data
     let mut in_data: Vec<u8> = Vec::new();
-    match file.read_to_end(&mut in_data){
-        Err(why) => panic!("Could not read data of {}: {}", display, Error::description(&why)),
-        Ok(file) => file,
-    };
+    if let Err(why) = file.read_to_end(&mut in_data) {
+        panic!("Could not read data of {}: {}", display, why);
+    }
 
-    let mut fibers: Vec<Fiber> = Vec::new();
-    let mut tmp_data: [u8; 4] = [0,0,0,0];
+    let n_scalars = header.n_scalars as usize;
+    let n_properties = header.n_properties as usize;
+    let value = |pos: usize| -> [u8; 4] {
+        [in_data[pos], in_data[pos + 1], in_data[pos + 2], in_data[pos + 3]]
+    };
 
+    let mut tracks: Vec<Track> = Vec::new();
     let mut data_pos = 0;
+    let mut complete = true;
 
     while data_pos < in_data.len() {
 
         //The first quartett is the number of stored Track Points
-        for i in 0..4 {
-            //Add four bytes to an int
-            tmp_data[i] = in_data[data_pos];
-            data_pos += 1;
+        if data_pos + 4 > in_data.len() {
+            complete = false;
+            break;
+        }
+        let num_points = u32::from_le_bytes(value(data_pos)) as usize;
+        data_pos += 4;
+
+        //Stop at a track that does not fit into the rest of the data
+        let size = num_points.saturating_mul(3 + n_scalars).saturating_add(n_properties).saturating_mul(4);
+        if size > in_data.len() - data_pos {
+            complete = false;
+            break;
         }
 
-        let num_points: u32 = unsafe {
-            mem::transmute(tmp_data)
+        //Read the whole track, every point has three coordinates followed by the scalars
+        let mut track = Track {
+            points: Vec::with_capacity(num_points),
+            scalars: Vec::with_capacity(num_points * n_scalars),
+            properties: Vec::with_capacity(n_properties),
         };
-
-        //Read the whole fiber
-        let mut tmp_fiber: Fiber = Vec::new();
-
         for _ in 0..num_points {
+            let mut xyz = [0.0f32; 3];
+            for coord in xyz.iter_mut() {
+                *coord = f32::from_le_bytes(value(data_pos));
+                data_pos += 4;
+            }
+            track.points.push(xyz);
 
-            //We do for the three coordinate the same:
-            //Convert four bytes to a f32
-            //Assign it to the right variable in tmp_position
-            //Add the read position to a fiber
-            let mut xyz: [f32; 3] = [0.0, 0.0, 0.0];
-
-            for n in 0..3 {
-                for i in 0..4 {
-                    tmp_data[i] = in_data[data_pos];
-                    data_pos += 1;
-                }
-                let tmp_coord: f32 = unsafe {
-                    mem::transmute(tmp_data)
-                };
-
-                //The TrackVis Coordinates are given in mm.
-                //Therefore we convert the TrackVis coordinate to coordinates in the NIfTI image.
-                xyz[n] = tmp_coord / header.voxel_size[n];
-                xyz[n] = xyz[n].trunc();
+            for _ in 0..n_scalars {
+                track.scalars.push(f32::from_le_bytes(value(data_pos)));
+                data_pos += 4;
             }
-            //println!("");
-            tmp_fiber.push( Position{x: xyz[0] as i32, y: xyz[1] as i32, z: xyz[2] as i32} );
+        }
+        for _ in 0..n_properties {
+            track.properties.push(f32::from_le_bytes(value(data_pos)));
+            data_pos += 4;
         }
 
-        //Add the read fiber to a list of fibers
-        fibers.push(tmp_fiber);
+        //Add the read track to a list of tracks
+        tracks.push(track);
     }
 
-    //TODO: Check read data size
-    /*
-    if header.n_count != 0 as u32 && header.n_count != (data_result/4 - fibers.len()) as u32 {
-        panic!("Something went wrong while reading the tracks. Expected {} tracks, but got {} tracks",
-               data_result/4, fibers.len());
-    }
-     */
+    (header, tracks, complete)
+}
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::{temp_file, test_header};
+
+    #[test]
+    fn write_read_write_is_byte_identical() {
+        let mut header = header_from_nifti(&test_header());
+        header.n_scalars = 1;
+        set_name(&mut header.scalar_name[0], "fa");
+        header.n_properties = 2;
+        set_name(&mut header.property_name[0], "cortex_label");
+        set_name(&mut header.property_name[1], "weight");
+        let tracks = vec![
+            Track { points: vec![[1.25, 2.5, 3.75], [4.0, 5.5, 6.125]], scalars: vec![0.5, 0.25], properties: vec![1003.0, 0.5] },
+            Track { points: vec![[7.5, 8.0, 9.0]], scalars: vec![0.75], properties: vec![2005.0, 1.5] },
+            Track { points: Vec::new(), scalars: Vec::new(), properties: vec![0.0, 0.0] },
+        ];
+
+        let first = temp_file("roundtrip_first", "trk");
+        let second = temp_file("roundtrip_second", "trk");
+        write_tracks(&header, &tracks, &first);
+        let (read_header, read) = read_tracks(&first);
+        write_tracks(&read_header, &read, &second);
+        let first_bytes = fs::read(&first).unwrap();
+        let second_bytes = fs::read(&second).unwrap();
+        fs::remove_file(&first).unwrap();
+        fs::remove_file(&second).unwrap();
+
+        assert!(first_bytes == second_bytes);
+        assert_eq!(read_header.n_count, 3);
+        assert_eq!(read.len(), tracks.len());
+        for (a, b) in read.iter().zip(tracks.iter()) {
+            assert_eq!(a.points, b.points);
+            assert_eq!(a.scalars, b.scalars);
+            assert_eq!(a.properties, b.properties);
+        }
+    }
 
-    return (header, fibers);
+    #[test]
+    fn write_stores_the_number_of_tracks() {
+        let nheader = test_header();
+        //Voxel centers of (1, 2, 3), (2, 2, 3) and (4, 5, 6) in RAS
+        let streamlines = vec![vec![[88.0, -122.0, -66.0], [86.0, -122.0, -66.0]], vec![[82.0, -116.0, -60.0]]];
+        let fibers = [vec![Position{x: 1, y: 2, z: 3}, Position{x: 2, y: 2, z: 3}], vec![Position{x: 4, y: 5, z: 6}]];
+        let file = temp_file("n_count", "trk");
+        write(&nheader, &streamlines, &[], &file);
+        let (header, read) = read(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(header.n_count, 2);
+        assert_eq!(read.len(), 2);
+        assert!(read[0] == fibers[0] && read[1] == fibers[1]);
+    }
 
+    #[test]
+    fn read_unchecked_stops_at_a_truncated_track() {
+        let mut header = header_from_nifti(&test_header());
+        header.n_count = 2;
+        let tracks = vec![
+            Track { points: vec![[1.0, 2.0, 3.0]], scalars: Vec::new(), properties: Vec::new() },
+            Track { points: vec![[4.0, 5.0, 6.0], [7.0, 8.0, 9.0]], scalars: Vec::new(), properties: Vec::new() },
+        ];
+        let file = temp_file("truncated", "trk");
+        write_tracks(&header, &tracks, &file);
+        let bytes = fs::read(&file).unwrap();
+        fs::write(&file, &bytes[..bytes.len() - 4]).unwrap();
+        let (read_header, read, complete) = read_tracks_unchecked(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert!(!complete);
+        assert_eq!(read_header.n_count, 2);
+        assert_eq!(read.len(), 1);
+        assert_eq!(read[0].points, tracks[0].points);
+    }
 }
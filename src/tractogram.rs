@@ -0,0 +1,268 @@
+//Selection of the tractogram format by file extension
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use nifti;
+use nifti::NIfTI1Header;
+use tck;
+use trackvis;
+use trackvis::{Streamline, Track, TrackVisHeader};
+use trx;
+use trx::TrxFile;
+use vtk;
+use vtk::PolyData;
+
+//Named values per point or per streamline, e.g. FA along the tracts or the cortex label of a tract
+#[derive(Clone)]
+pub struct DataArray {
+    pub name: String,       //Name of the array.
+    pub dim: usize,         //Number of values (components) per point/streamline.
+    pub data: Vec<f32>,     //Values, dim values per point/streamline.
+}
+
+//Streamlines as stored in the input file. They are kept to write them again without the loss of
+//precision of the voxel positions, together with their scalars and point data.
+pub enum Source {
+    TrackVis(Box<TrackVisHeader>, Vec<Track>),
+    Tck(Vec<Vec<[f32; 3]>>),    //Points in RAS (mm).
+    Vtk(PolyData),
+    Trx(Box<TrxFile>),
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Format {
+    TrackVis,   //.trk
+    Tck,        //.tck
+    Trx,        //.trx or a TRX directory
+    Vtk,        //.vtk (legacy) or .vtp (XML) PolyData
+}
+
+//Determine the tractogram format from the extension of the file name
+pub fn format(file_name: &str) -> Format {
+    if Path::new(file_name).is_dir() {
+        return Format::Trx;
+    }
+
+    let extension = Path::new(file_name).extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    match extension.as_deref() {
+        Some("trk") => Format::TrackVis,
+        Some("tck") => Format::Tck,
+        Some("trx") => Format::Trx,
+        Some("vtk") | Some("vtp") => Format::Vtk,
+        _ => panic!("Unknown tractogram format of {}! Supported are .trk, .tck, .trx, .vtk and .vtp files.", file_name),
+    }
+}
+
+//Determine the format of an output tractogram, TRX directories are read but only written as zipped .trx file
+pub fn output_format(file_name: &str) -> Format {
+    if Path::new(file_name).is_dir() {
+        panic!("Could not write {}: TRX directories can only be read, use a .trx file as output!", file_name);
+    }
+    format(file_name)
+}
+
+//Read the streamlines of a tractogram in voxel coordinates of the image described by nheader
+pub fn read(file_name: &str, nheader: &NIfTI1Header) -> Vec<Streamline> {
+    read_source(file_name, nheader).1
+}
+
+//Convert points given in RAS (mm) to voxel coordinates of the image, ras_to_vox is the inverse of its affine
+pub fn streamline_from_ras(points: &[[f32; 3]], ras_to_vox: &[[f32; 4]; 4]) -> Streamline {
+    points.iter().map(|p| nifti::apply_affine(ras_to_vox, *p)).collect()
+}
+
+//Return true if the format can store values per streamline
+pub fn has_properties(format: Format) -> bool {
+    format != Format::Tck
+}
+
+//Write streamlines given in RAS (mm) in the space of the image described by nheader together with values per streamline.
+//The values are stored as track properties (.trk), dps arrays (.trx) or cell data (.vtk, .vtp).
+pub fn write(nheader: &NIfTI1Header, streamlines: &[Vec<[f32; 3]>], properties: &[DataArray], file_name: &str) {
+    match output_format(file_name) {
+        Format::TrackVis => trackvis::write(nheader, streamlines, properties, file_name),
+        Format::Tck => tck::write(streamlines, file_name),
+        Format::Trx => {
+            let mut trx = trx::from_streamlines(nheader, streamlines);
+            trx.dps = properties.to_vec();
+            trx::write(&trx, file_name);
+        },
+        Format::Vtk => {
+            let mut poly = vtk::from_streamlines(streamlines);
+            poly.cell_data = properties.to_vec();
+            vtk::write(&poly, file_name);
+        },
+    }
+}
+
+//Read a tractogram and keep the stored streamlines to write them again without loss.
+//The streamlines are returned in voxel coordinates of the image described by nheader,
+//TrackVis tracks are used in the voxel grid of their header.
+pub fn read_source(file_name: &str, nheader: &NIfTI1Header) -> (Source, Vec<Streamline>) {
+    let source = match format(file_name) {
+        Format::TrackVis => {
+            let (header, tracks) = trackvis::read_tracks(file_name);
+            Source::TrackVis(Box::new(header), tracks)
+        },
+        Format::Tck => Source::Tck(tck::read(file_name).1),
+        Format::Vtk => Source::Vtk(vtk::read(file_name)),
+        Format::Trx => Source::Trx(Box::new(trx::read(file_name))),
+    };
+
+    let streamlines = match source {
+        Source::TrackVis(ref header, ref tracks) => trackvis::streamlines(header, tracks),
+        _ => {
+            let ras_to_vox = nifti::inverse_affine(&nifti::affine(nheader));
+            let all: Vec<usize> = (0..count(&source)).collect();
+            ras_streamlines(&source, &all).iter().map(|s| streamline_from_ras(s, &ras_to_vox)).collect()
+        },
+    };
+    (source, streamlines)
+}
+
+//Return the number of streamlines of the source
+fn count(source: &Source) -> usize {
+    match source {
+        Source::TrackVis(_, tracks) => tracks.len(),
+        Source::Tck(streamlines) => streamlines.len(),
+        Source::Vtk(poly) => poly.lines.len(),
+        Source::Trx(trx) => trx.offsets.len(),
+    }
+}
+
+//Return the selected streamlines of the source in RAS (mm)
+fn ras_streamlines(source: &Source, selection: &[usize]) -> Vec<Vec<[f32; 3]>> {
+    match source {
+        Source::TrackVis(header, tracks) => {
+            let voxmm_to_ras = trackvis::voxmm_to_ras(header);
+            selection.iter()
+                .map(|i| tracks[*i].points.iter().map(|p| nifti::apply_affine(&voxmm_to_ras, *p)).collect())
+                .collect()
+        },
+        Source::Tck(streamlines) => selection.iter().map(|i| streamlines[*i].clone()).collect(),
+        Source::Vtk(poly) => selection.iter().map(|i| poly.lines[*i].iter().map(|p| poly.points[*p]).collect()).collect(),
+        Source::Trx(trx) => selection.iter().map(|i| trx.streamline(*i).to_vec()).collect(),
+    }
+}
+
+//Return the values of a single valued array per streamline stored in the source
+//(track property, dps array or cell data), None if there is no such array
+pub fn streamline_values(source: &Source, name: &str) -> Option<Vec<f32>> {
+    let find = |arrays: &[DataArray]| arrays.iter().find(|a| a.name == name && a.dim == 1).map(|a| a.data.clone());
+    match source {
+        Source::TrackVis(header, tracks) => {
+            let mut property = [0u8; 20];
+            trackvis::set_name(&mut property, name);
+            let n = header.n_properties as usize;
+            header.property_name[..n].iter().position(|p| *p == property)
+                .map(|index| tracks.iter().map(|t| t.properties[index]).collect())
+        },
+        Source::Vtk(poly) => find(&poly.cell_data),
+        Source::Trx(trx) => find(&trx.dps),
+        Source::Tck(_) => None,
+    }
+}
+
+//Read a text file with one value per streamline (e.g. the weights of MRtrix tcksift2).
+//The values are separated by white space, lines starting with # are comments.
+pub fn read_values(file_name: &str) -> Vec<f32> {
+    let path = Path::new(file_name);
+    let display = path.display();
+    let mut file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let mut text = String::new();
+    if let Err(why) = file.read_to_string(&mut text) {
+        panic!("Could not read data of {}: {}", display, why);
+    }
+
+    text.lines()
+        .filter(|line| !line.trim_start().starts_with('#'))
+        .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+        .filter(|value| !value.is_empty())
+        .map(|value| match value.parse::<f32>() {
+            Ok(v) => v,
+            Err(_) => panic!("Could not parse the value {} in {}!", value, display),
+        })
+        .collect()
+}
+
+//Write the selected streamlines of the source together with values per streamline. The points are written as stored.
+//If the output has the format of the source, the values are added to (or replace) the stored properties,
+//otherwise the image described by nheader is the reference space of the output.
+pub fn write_selection(source: &Source, nheader: &NIfTI1Header, selection: &[usize],
+                       properties: &[DataArray], file_name: &str) {
+    match (output_format(file_name), source) {
+        (Format::TrackVis, Source::TrackVis(header, tracks)) => {
+            let mut header = **header;
+            let mut tracks: Vec<Track> = selection.iter().map(|i| tracks[*i].clone()).collect();
+
+            for property in properties.iter() {
+                let mut name = [0u8; 20];
+                trackvis::set_name(&mut name, &property.name);
+
+                let n = header.n_properties as usize;
+                let index = match header.property_name[..n].iter().position(|p| *p == name) {
+                    Some(index) => index,
+                    None if n < 10 => {
+                        header.property_name[n] = name;
+                        header.n_properties += 1;
+                        for track in tracks.iter_mut() {
+                            track.properties.push(0.0);
+                        }
+                        n
+                    },
+                    None => panic!("Could not add the property {} to {}, TrackVis supports 10 properties only.", property.name, file_name),
+                };
+                for (track, value) in tracks.iter_mut().zip(property.data.iter()) {
+                    track.properties[index] = *value;
+                }
+            }
+            trackvis::write_tracks(&header, &tracks, file_name);
+        },
+        (Format::Trx, Source::Trx(trx)) => {
+            let mut trx = trx::select(trx, selection);
+            for property in properties.iter() {
+                trx.dps.retain(|a| a.name != property.name);
+                trx.dps.push(property.clone());
+            }
+            trx::write(&trx, file_name);
+        },
+        (Format::Vtk, Source::Vtk(poly)) => {
+            let mut poly = vtk::select(poly, selection);
+            for property in properties.iter() {
+                poly.cell_data.retain(|a| a.name != property.name);
+                poly.cell_data.push(property.clone());
+            }
+            vtk::write(&poly, file_name);
+        },
+        _ => write(nheader, &ras_streamlines(source, selection), properties, file_name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn format_follows_the_extension() {
+        assert_eq!(format("tracts.TRK"), Format::TrackVis);
+        assert_eq!(format("tracts.tck"), Format::Tck);
+        assert_eq!(format("tracts.trx"), Format::Trx);
+        assert_eq!(format("tracts.vtp"), Format::Vtk);
+        assert_eq!(format(&env::temp_dir().to_string_lossy()), Format::Trx);
+    }
+
+    #[test]
+    #[should_panic(expected = "TRX directories can only be read")]
+    fn output_format_rejects_directories() {
+        output_format(&env::temp_dir().to_string_lossy());
+    }
+}
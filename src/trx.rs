@@ -0,0 +1,484 @@
+//Definitions of the TRX datatypes
+//A TRX tractogram is a zip archive (or a directory) of flat little-endian arrays:
+//header.json, positions.3.<dtype>, offsets.<dtype>, dpv/, dps/, groups/ and dpg/.
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use std::ops::Range;
+use std::path::Path;
+
+use zip;
+
+use nifti;
+use nifti::NIfTI1Header;
+use tractogram::DataArray;
+
+//Streamlines belonging to a group, e.g. groups/CST_left.uint32
+pub struct Group {
+    pub name: String,       //Name of the group.
+    pub indices: Vec<u32>,  //Indices of the streamlines in the group.
+}
+
+pub struct TrxFile {
+    pub dimensions: [u16; 3],               //Dimension of the reference image.
+    pub voxel_to_rasmm: [[f32; 4]; 4],      //Affine of the reference image.
+    pub positions: Vec<[f32; 3]>,           //All vertices in RAS (mm).
+    pub offsets: Vec<usize>,                //Index of the first vertex of every streamline.
+    pub dpv: Vec<DataArray>,                //Data per vertex.
+    pub dps: Vec<DataArray>,                //Data per streamline.
+    pub groups: Vec<Group>,                 //Groups of streamlines.
+}
+
+impl TrxFile {
+    //Return the range of the vertices of a streamline
+    fn vertices(&self, streamline: usize) -> Range<usize> {
+        let end = self.offsets.get(streamline + 1).cloned().unwrap_or(self.positions.len());
+        self.offsets[streamline]..end
+    }
+
+    //Return the vertices of a streamline in RAS (mm)
+    pub fn streamline(&self, streamline: usize) -> &[[f32; 3]] {
+        &self.positions[self.vertices(streamline)]
+    }
+}
+
+//Create a TRX tractogram of streamlines given in RAS (mm) in the space of the image described by nheader
+pub fn from_streamlines(nheader: &NIfTI1Header, streamlines: &[Vec<[f32; 3]>]) -> TrxFile {
+    let mut trx = TrxFile {
+        dimensions: [nheader.dim[1], nheader.dim[2], nheader.dim[3]],
+        voxel_to_rasmm: nifti::affine(nheader),
+        positions: Vec::new(),
+        offsets: Vec::with_capacity(streamlines.len()),
+        dpv: Vec::new(),
+        dps: Vec::new(),
+        groups: Vec::new(),
+    };
+    for streamline in streamlines.iter() {
+        trx.offsets.push(trx.positions.len());
+        trx.positions.extend_from_slice(streamline);
+    }
+    trx
+}
+
+//Return the streamlines with the given indices together with their dpv and dps values.
+//The groups keep the selected streamlines and refer to their new indices.
+pub fn select(trx: &TrxFile, streamlines: &[usize]) -> TrxFile {
+    let mut selected = TrxFile {
+        dimensions: trx.dimensions,
+        voxel_to_rasmm: trx.voxel_to_rasmm,
+        positions: Vec::new(),
+        offsets: Vec::with_capacity(streamlines.len()),
+        dpv: trx.dpv.iter().map(|a| DataArray{ name: a.name.clone(), dim: a.dim, data: Vec::new() }).collect(),
+        dps: trx.dps.iter().map(|a| DataArray{ name: a.name.clone(), dim: a.dim, data: Vec::new() }).collect(),
+        groups: Vec::new(),
+    };
+
+    let mut new_index = vec![None; trx.offsets.len()];
+    for (n, s) in streamlines.iter().enumerate() {
+        new_index[*s] = Some(n as u32);
+        let vertices = trx.vertices(*s);
+        selected.offsets.push(selected.positions.len());
+        selected.positions.extend_from_slice(&trx.positions[vertices.clone()]);
+        for (array, source) in selected.dpv.iter_mut().zip(trx.dpv.iter()) {
+            array.data.extend_from_slice(&source.data[vertices.start * source.dim..vertices.end * source.dim]);
+        }
+        for (array, source) in selected.dps.iter_mut().zip(trx.dps.iter()) {
+            array.data.extend_from_slice(&source.data[s * source.dim..(s + 1) * source.dim]);
+        }
+    }
+
+    selected.groups = trx.groups.iter().map(|g| Group {
+        name: g.name.clone(),
+        indices: g.indices.iter().filter_map(|i| new_index[*i as usize]).collect(),
+    }).collect();
+    selected
+}
+
+//Return the numbers following "key": in a JSON text, flattening nested arrays
+fn json_numbers(text: &str, key: &str) -> Vec<f64> {
+    let pattern = format!("\"{}\"", key);
+    let start = match text.find(&pattern) {
+        Some(i) => i + pattern.len(),
+        None => return Vec::new(),
+    };
+    let rest = match text[start..].find(':') {
+        Some(i) => &text[start + i + 1..],
+        None => return Vec::new(),
+    };
+
+    let mut numbers = Vec::new();
+    let mut depth = 0;
+    let mut token = String::new();
+    for c in rest.chars() {
+        match c {
+            '[' => depth += 1,
+            ']' | ',' | '}' => {
+                if !token.is_empty() {
+                    numbers.push(token.parse().unwrap_or(0.0));
+                    token.clear();
+                }
+                if c == ']' {
+                    depth -= 1;
+                }
+                if depth <= 0 {
+                    break;
+                }
+            },
+            c if c.is_whitespace() => {},
+            c => token.push(c),
+        }
+    }
+    numbers
+}
+
+//Convert little-endian bytes of the given TRX data type into floats
+fn decode(bytes: &[u8], dtype: &str, display: &str) -> Vec<f64> {
+    fn chunks<T, F: Fn(&[u8]) -> T>(bytes: &[u8], size: usize, f: F) -> Vec<T> {
+        bytes.chunks(size).filter(|c| c.len() == size).map(f).collect()
+    }
+
+    match dtype {
+        "int8" => chunks(bytes, 1, |b| b[0] as i8 as f64),
+        "uint8" => chunks(bytes, 1, |b| b[0] as f64),
+        "int16" => chunks(bytes, 2, |b| i16::from_le_bytes([b[0], b[1]]) as f64),
+        "uint16" => chunks(bytes, 2, |b| u16::from_le_bytes([b[0], b[1]]) as f64),
+        "int32" => chunks(bytes, 4, |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "uint32" => chunks(bytes, 4, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "int64" => chunks(bytes, 8, |b| i64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64),
+        "uint64" => chunks(bytes, 8, |b| u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]) as f64),
+        "float16" => chunks(bytes, 2, |b| half_to_f32(u16::from_le_bytes([b[0], b[1]])) as f64),
+        "float32" => chunks(bytes, 4, |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64),
+        "float64" => chunks(bytes, 8, |b| f64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]])),
+        _ => panic!("Unsupported data type {} in {}!", dtype, display),
+    }
+}
+
+//Convert an IEEE 754 half precision float to f32
+fn half_to_f32(h: u16) -> f32 {
+    let sign = if h & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((h >> 10) & 0x1f) as i32;
+    let mantissa = (h & 0x3ff) as f32;
+
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+//Split an array file name into name, dimension and data type, e.g. "positions.3.float32"
+fn split_name(file_name: &str) -> (String, usize, String) {
+    let parts: Vec<&str> = file_name.split('.').collect();
+    if parts.len() < 2 {
+        return (file_name.to_string(), 1, String::new());
+    }
+
+    let dtype = parts[parts.len() - 1].to_string();
+    if parts.len() > 2 {
+        if let Ok(dim) = parts[parts.len() - 2].parse() {
+            return (parts[..parts.len() - 2].join("."), dim, dtype);
+        }
+    }
+    (parts[..parts.len() - 1].join("."), 1, dtype)
+}
+
+//Collect all files of a TRX directory with their relative path
+fn read_directory(root: &Path, dir: &Path, entries: &mut Vec<(String, Vec<u8>)>) {
+    let display = dir.display();
+    let listing = match fs::read_dir(dir) {
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(listing) => listing,
+    };
+
+    for entry in listing {
+        let path = match entry {
+            Err(why) => panic!("Could not read {}: {}", display, why),
+            Ok(entry) => entry.path(),
+        };
+        if path.is_dir() {
+            read_directory(root, &path, entries);
+            continue;
+        }
+
+        let mut data = Vec::new();
+        if let Err(why) = File::open(&path).and_then(|mut f| f.read_to_end(&mut data)) {
+            panic!("Could not read {}: {}", path.display(), why);
+        }
+        let name = path.strip_prefix(root).unwrap().components()
+            .map(|c| c.as_os_str().to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join("/");
+        entries.push((name, data));
+    }
+}
+
+//Collect all files of a zipped TRX file with their path inside the archive
+fn read_archive(path: &Path) -> Vec<(String, Vec<u8>)> {
+    let display = path.display();
+    let file = match File::open(path) {
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+    let mut archive = match zip::ZipArchive::new(file) {
+        Err(why) => panic!("Could not open {} as zip archive: {}", display, why),
+        Ok(archive) => archive,
+    };
+
+    let mut entries = Vec::new();
+    for i in 0..archive.len() {
+        let mut entry = match archive.by_index(i) {
+            Err(why) => panic!("Could not read {}: {}", display, why),
+            Ok(entry) => entry,
+        };
+        if entry.is_dir() {
+            continue;
+        }
+
+        let name = entry.name().to_string();
+        let mut data = Vec::new();
+        if let Err(why) = entry.read_to_end(&mut data) {
+            panic!("Could not read {} in {}: {}", name, display, why);
+        }
+        entries.push((name, data));
+    }
+    entries
+}
+
+//Read a TRX file (zip archive or directory)
+pub fn read(file_name: &str) -> TrxFile {
+    let path = Path::new(file_name);
+    let display = path.display().to_string();
+
+    let entries = if path.is_dir() {
+        let mut entries = Vec::new();
+        read_directory(path, path, &mut entries);
+        entries
+    } else {
+        read_archive(path)
+    };
+
+    //Read header
+    let header = match entries.iter().find(|e| e.0 == "header.json") {
+        Some(e) => String::from_utf8_lossy(&e.1).into_owned(),
+        None => panic!("Could not find header.json in {}!", display),
+    };
+
+    let mut trx = TrxFile {
+        dimensions: [0; 3],
+        voxel_to_rasmm: [[0.0; 4]; 4],
+        positions: Vec::new(),
+        offsets: Vec::new(),
+        dpv: Vec::new(),
+        dps: Vec::new(),
+        groups: Vec::new(),
+    };
+
+    let dimensions = json_numbers(&header, "DIMENSIONS");
+    let affine = json_numbers(&header, "VOXEL_TO_RASMM");
+    if dimensions.len() != 3 || affine.len() != 16 {
+        panic!("Wrong header in {}!", display);
+    }
+    for (i, d) in dimensions.iter().enumerate() {
+        trx.dimensions[i] = *d as u16;
+    }
+    for (i, a) in affine.iter().enumerate() {
+        trx.voxel_to_rasmm[i / 4][i % 4] = *a as f32;
+    }
+    let nb_streamlines = json_numbers(&header, "NB_STREAMLINES").first().cloned().unwrap_or(0.0) as usize;
+    let nb_vertices = json_numbers(&header, "NB_VERTICES").first().cloned().unwrap_or(0.0) as usize;
+
+    //Read the arrays
+    for (entry_name, data) in entries.iter() {
+        let (folder, file) = match entry_name.rfind('/') {
+            Some(i) => (&entry_name[..i], &entry_name[i + 1..]),
+            None => ("", &entry_name[..]),
+        };
+        let (name, dim, dtype) = split_name(file);
+
+        match (folder, name.as_str()) {
+            ("", "header") => {},
+            ("", "positions") => {
+                if dim != 3 {
+                    panic!("Positions in {} have to be three dimensional!", display);
+                }
+                trx.positions = decode(data, &dtype, &display).chunks(3)
+                    .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32])
+                    .collect();
+            },
+            ("", "offsets") => {
+                trx.offsets = decode(data, &dtype, &display).iter().map(|o| *o as usize).collect();
+            },
+            ("dpv", _) => trx.dpv.push(DataArray {
+                name: name.clone(),
+                dim,
+                data: decode(data, &dtype, &display).iter().map(|v| *v as f32).collect(),
+            }),
+            ("dps", _) => trx.dps.push(DataArray {
+                name: name.clone(),
+                dim,
+                data: decode(data, &dtype, &display).iter().map(|v| *v as f32).collect(),
+            }),
+            ("groups", _) => trx.groups.push(Group {
+                name: name.clone(),
+                indices: decode(data, &dtype, &display).iter().map(|v| *v as u32).collect(),
+            }),
+            _ => {},
+        }
+    }
+
+    //Older files store a closing offset
+    if trx.offsets.len() == nb_streamlines + 1 {
+        trx.offsets.pop();
+    }
+
+    //Check integrety
+    if trx.positions.len() != nb_vertices || trx.offsets.len() != nb_streamlines {
+        panic!("Something went wrong while reading {}. Expected {} streamlines with {} vertices, but got {} streamlines with {} vertices",
+               display, nb_streamlines, nb_vertices, trx.offsets.len(), trx.positions.len());
+    }
+    for dps in trx.dps.iter() {
+        if dps.data.len() != nb_streamlines * dps.dim {
+            panic!("Wrong size of dps/{} in {}!", dps.name, display);
+        }
+    }
+    for dpv in trx.dpv.iter() {
+        if dpv.data.len() != nb_vertices * dpv.dim {
+            panic!("Wrong size of dpv/{} in {}!", dpv.name, display);
+        }
+    }
+    for group in trx.groups.iter() {
+        if group.indices.iter().any(|i| *i as usize >= nb_streamlines) {
+            panic!("Group {} in {} refers to a missing streamline!", group.name, display);
+        }
+    }
+
+    for i in 0..trx.offsets.len() {
+        let vertices = trx.vertices(i);
+        if vertices.start > vertices.end || vertices.end > nb_vertices {
+            panic!("Wrong offsets in {}!", display);
+        }
+    }
+
+    trx
+}
+
+//Write a TRX tractogram to a zipped TRX file
+pub fn write(trx: &TrxFile, file_name: &str) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display();
+    let file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let nb_streamlines = trx.offsets.len();
+    let nb_vertices = trx.positions.len();
+
+    //Fill header
+    let affine_rows: Vec<String> = trx.voxel_to_rasmm.iter()
+        .map(|r| format!("[{}, {}, {}, {}]", r[0], r[1], r[2], r[3]))
+        .collect();
+    let header = format!("{{\"DIMENSIONS\": [{}, {}, {}], \"VOXEL_TO_RASMM\": [{}], \"NB_STREAMLINES\": {}, \"NB_VERTICES\": {}}}",
+                         trx.dimensions[0], trx.dimensions[1], trx.dimensions[2], affine_rows.join(", "),
+                         nb_streamlines, nb_vertices);
+
+    let positions: Vec<u8> = trx.positions.iter().flat_map(|p| p.iter().flat_map(|c| c.to_le_bytes().to_vec())).collect();
+    let offsets: Vec<u8> = trx.offsets.iter().flat_map(|o| (*o as u64).to_le_bytes().to_vec()).collect();
+
+    let mut entries: Vec<(String, Vec<u8>)> = vec![
+        ("header.json".to_string(), header.into_bytes()),
+        ("positions.3.float32".to_string(), positions),
+        ("offsets.uint64".to_string(), offsets),
+    ];
+    for (folder, arrays, count) in [("dpv", &trx.dpv, nb_vertices), ("dps", &trx.dps, nb_streamlines)].iter() {
+        for array in arrays.iter() {
+            if array.data.len() != count * array.dim {
+                panic!("Wrong size of {}/{} for {}!", folder, array.name, display);
+            }
+            let name = if array.dim == 1 {
+                format!("{}/{}.float32", folder, array.name)
+            } else {
+                format!("{}/{}.{}.float32", folder, array.name, array.dim)
+            };
+            entries.push((name, array.data.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()));
+        }
+    }
+    for group in trx.groups.iter() {
+        entries.push((format!("groups/{}.uint32", group.name),
+                      group.indices.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect()));
+    }
+
+    //TRX arrays are memory mapped by other tools, so they are stored uncompressed
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Stored)
+        .large_file(12 * nb_vertices > u32::MAX as usize);
+    let mut archive = zip::ZipWriter::new(file);
+    for (name, data) in entries.iter() {
+        if let Err(why) = archive.start_file(name.as_str(), options) {
+            panic!("Could not write {} to {}: {}", name, display, why);
+        }
+        if let Err(why) = archive.write_all(data) {
+            panic!("Could not write {} to {}: {}", name, display, why);
+        }
+    }
+    if let Err(why) = archive.finish() {
+        panic!("Could not write {}: {}", display, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::{temp_file, test_header};
+
+    fn test_trx() -> TrxFile {
+        let streamlines = vec![
+            vec![[80.5, -120.25, -60.0], [78.0, -118.0, -58.5]],
+            vec![[70.0, -110.0, -50.0]],
+            vec![[60.0, -100.0, -40.0], [61.0, -101.0, -41.0], [62.0, -102.0, -42.0]],
+        ];
+        let mut trx = from_streamlines(&test_header(), &streamlines);
+        trx.dpv.push(DataArray { name: "fa".to_string(), dim: 1, data: vec![0.1, 0.2, 0.3, 0.4, 0.5, 0.6] });
+        trx.dps.push(DataArray { name: "color".to_string(), dim: 3, data: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0] });
+        trx.groups.push(Group { name: "odd".to_string(), indices: vec![0, 2] });
+        trx
+    }
+
+    #[test]
+    fn write_read_keeps_positions_and_data() {
+        let trx = test_trx();
+        let file = temp_file("roundtrip", "trx");
+        write(&trx, &file);
+        let read_trx = read(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(read_trx.dimensions, [12, 10, 8]);
+        assert_eq!(read_trx.voxel_to_rasmm, trx.voxel_to_rasmm);
+        assert_eq!(read_trx.positions, trx.positions);
+        assert_eq!(read_trx.offsets, trx.offsets);
+        assert_eq!(read_trx.dpv.len(), 1);
+        assert_eq!((read_trx.dpv[0].name.as_str(), read_trx.dpv[0].dim), ("fa", 1));
+        assert_eq!(read_trx.dpv[0].data, trx.dpv[0].data);
+        assert_eq!(read_trx.dps.len(), 1);
+        assert_eq!((read_trx.dps[0].name.as_str(), read_trx.dps[0].dim), ("color", 3));
+        assert_eq!(read_trx.dps[0].data, trx.dps[0].data);
+        assert_eq!(read_trx.groups.len(), 1);
+        assert_eq!((read_trx.groups[0].name.as_str(), &read_trx.groups[0].indices), ("odd", &vec![0, 2]));
+    }
+
+    #[test]
+    fn select_keeps_data_of_the_selected_streamlines() {
+        let selected = select(&test_trx(), &[2, 1]);
+
+        assert_eq!(selected.offsets, vec![0, 3]);
+        assert_eq!(selected.streamline(0), &[[60.0, -100.0, -40.0], [61.0, -101.0, -41.0], [62.0, -102.0, -42.0]]);
+        assert_eq!(selected.streamline(1), &[[70.0, -110.0, -50.0]]);
+        assert_eq!(selected.dpv[0].data, vec![0.4, 0.5, 0.6, 0.3]);
+        assert_eq!(selected.dps[0].data, vec![0.0, 0.0, 1.0, 0.0, 1.0, 0.0]);
+        assert_eq!(selected.groups[0].indices, vec![0]);
+    }
+}
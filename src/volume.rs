@@ -0,0 +1,54 @@
+//Selection of the volume format by file extension
+//All volumes are handled as NIfTI1 header and data, MGH volumes are converted on reading and writing.
+use nifti;
+use nifti::{NIfTI1Data, NIfTI1Header};
+use mgh;
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Format {
+    NIfTI1,     //.nii
+    Mgh,        //.mgh, .mgz or .mgh.gz
+}
+
+fn try_format(file_name: &str) -> Option<Format> {
+    let lower = file_name.to_lowercase();
+    if lower.ends_with(".nii") {
+        Some(Format::NIfTI1)
+    } else if lower.ends_with(".mgh") || lower.ends_with(".mgz") || lower.ends_with(".mgh.gz") {
+        Some(Format::Mgh)
+    } else {
+        None
+    }
+}
+
+//Determine the volume format from the extension of the file name
+pub fn format(file_name: &str) -> Format {
+    match try_format(file_name) {
+        Some(format) => format,
+        None => panic!("Unknown volume format of {}! Supported are .nii, .mgh and .mgz files.", file_name),
+    }
+}
+
+//Return true if the file name has the extension of a volume format
+pub fn is_volume(file_name: &str) -> bool {
+    try_format(file_name).is_some()
+}
+
+//Read a volume as NIfTI1 header and data
+pub fn read(file_name: &str) -> (NIfTI1Header, NIfTI1Data) {
+    match format(file_name) {
+        Format::NIfTI1 => nifti::read(file_name),
+        Format::Mgh => {
+            let (header, data) = mgh::read(file_name);
+            (mgh::nifti_header(&header), data)
+        },
+    }
+}
+
+//Write a volume given as NIfTI1 header and data
+pub fn write(header: NIfTI1Header, data: NIfTI1Data, file_name: &str) {
+    match format(file_name) {
+        Format::NIfTI1 => nifti::write(header, data, file_name),
+        Format::Mgh => mgh::write(&mgh::from_nifti_header(&header), &data, file_name),
+    }
+}
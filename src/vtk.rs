@@ -0,0 +1,838 @@
+//Definitions of the VTK PolyData datatypes
+//Streamlines are stored as polylines, either in the legacy format (.vtk) or in the XML format (.vtp).
+use std::fs::File;
+use std::io::prelude::*;
+use std::path::Path;
+
+use flate2::read::ZlibDecoder;
+
+use tractogram::DataArray;
+
+pub struct PolyData {
+    pub points: Vec<[f32; 3]>,          //All points in RAS (mm).
+    pub lines: Vec<Vec<usize>>,         //Point indices of every polyline.
+    pub point_data: Vec<DataArray>,     //Data per point, e.g. FA along the tracts.
+    pub cell_data: Vec<DataArray>,      //Data per polyline.
+}
+
+//Convert streamlines given in RAS (mm) to polylines
+pub fn from_streamlines(streamlines: &[Vec<[f32; 3]>]) -> PolyData {
+    let mut poly = PolyData {
+        points: Vec::new(),
+        lines: Vec::new(),
+        point_data: Vec::new(),
+        cell_data: Vec::new(),
+    };
+
+    for streamline in streamlines.iter() {
+        let start = poly.points.len();
+        poly.points.extend_from_slice(streamline);
+        poly.lines.push((start..poly.points.len()).collect());
+    }
+    poly
+}
+
+//Return the polylines with the given indices, only the points and point data of these lines are kept
+pub fn select(poly: &PolyData, lines: &[usize]) -> PolyData {
+    let mut selected = PolyData {
+        points: Vec::new(),
+        lines: Vec::new(),
+        point_data: poly.point_data.iter().map(|a| DataArray{ name: a.name.clone(), dim: a.dim, data: Vec::new() }).collect(),
+        cell_data: poly.cell_data.iter().map(|a| DataArray{ name: a.name.clone(), dim: a.dim, data: Vec::new() }).collect(),
+    };
+
+    for l in lines.iter() {
+        let mut line = Vec::with_capacity(poly.lines[*l].len());
+        for p in poly.lines[*l].iter() {
+            line.push(selected.points.len());
+            selected.points.push(poly.points[*p]);
+            for (array, source) in selected.point_data.iter_mut().zip(poly.point_data.iter()) {
+                array.data.extend_from_slice(&source.data[p * source.dim..(p + 1) * source.dim]);
+            }
+        }
+        selected.lines.push(line);
+        for (array, source) in selected.cell_data.iter_mut().zip(poly.cell_data.iter()) {
+            array.data.extend_from_slice(&source.data[l * source.dim..(l + 1) * source.dim]);
+        }
+    }
+    selected
+}
+
+//Size in bytes of a VTK data type (legacy and XML names)
+fn type_size(dtype: &str, display: &str) -> usize {
+    match dtype {
+        "bit" | "char" | "unsigned_char" | "Int8" | "UInt8" => 1,
+        "short" | "unsigned_short" | "Int16" | "UInt16" => 2,
+        "int" | "unsigned_int" | "float" | "Int32" | "UInt32" | "Float32" | "vtktypeint32" => 4,
+        "long" | "unsigned_long" | "double" | "Int64" | "UInt64" | "Float64" | "vtktypeint64" | "vtktypeuint64" => 8,
+        _ => panic!("Unsupported data type {} in {}!", dtype, display),
+    }
+}
+
+//Convert binary values of a VTK data type to floats
+fn decode(bytes: &[u8], dtype: &str, big_endian: bool, display: &str) -> Vec<f64> {
+    let size = type_size(dtype, display);
+    let mut values = Vec::with_capacity(bytes.len() / size);
+
+    for chunk in bytes.chunks(size).filter(|c| c.len() == size) {
+        let mut b = [0u8; 8];
+        b[..size].copy_from_slice(chunk);
+        if big_endian {
+            b[..size].reverse();
+        }
+
+        let value = match dtype {
+            "char" | "Int8" => b[0] as i8 as f64,
+            "bit" | "unsigned_char" | "UInt8" => b[0] as f64,
+            "short" | "Int16" => i16::from_le_bytes([b[0], b[1]]) as f64,
+            "unsigned_short" | "UInt16" => u16::from_le_bytes([b[0], b[1]]) as f64,
+            "int" | "Int32" | "vtktypeint32" => i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "unsigned_int" | "UInt32" => u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "float" | "Float32" => f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64,
+            "long" | "Int64" | "vtktypeint64" => i64::from_le_bytes(b) as f64,
+            "unsigned_long" | "UInt64" | "vtktypeuint64" => u64::from_le_bytes(b) as f64,
+            _ => f64::from_le_bytes(b),
+        };
+        values.push(value);
+    }
+    values
+}
+
+//Convert RAS to LPS and vice versa
+fn flip_lps(points: &mut [[f32; 3]]) {
+    for p in points.iter_mut() {
+        p[0] = -p[0];
+        p[1] = -p[1];
+    }
+}
+
+//Read a VTK file (.vtk or .vtp)
+pub fn read(file_name: &str) -> PolyData {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display().to_string();
+    let mut file = match File::open(path){
+        Err(why) => panic!("Could not open {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let mut in_data: Vec<u8> = Vec::new();
+    if let Err(why) = file.read_to_end(&mut in_data) {
+        panic!("Could not read data of {}: {}", display, why);
+    }
+
+    let poly = if in_data.starts_with(b"# vtk DataFile") {
+        read_legacy(&in_data, &display)
+    } else {
+        read_xml(&in_data, &display)
+    };
+
+    if poly.lines.iter().flatten().any(|i| *i >= poly.points.len()) {
+        panic!("A line in {} refers to a missing point!", display);
+    }
+    poly
+}
+
+//Write polylines to a VTK file, the format is selected by the extension (.vtk or .vtp)
+pub fn write(poly: &PolyData, file_name: &str) {
+
+    //Open file
+    let path = Path::new(file_name);
+    let display = path.display().to_string();
+    let mut file = match File::create(path){
+        Err(why) => panic!("Could not create {}: {}", display, why),
+        Ok(file) => file,
+    };
+
+    let xml = path.extension().map(|e| e.to_string_lossy().to_lowercase() == "vtp").unwrap_or(false);
+    let out_data = if xml { write_xml(poly) } else { write_legacy(poly) };
+
+    if let Err(why) = file.write_all(&out_data) {
+        panic!("Could not write data of {}: {}", display, why);
+    }
+}
+
+/*
+ Legacy format
+*/
+
+//Reads the keyword lines and the ASCII or binary data blocks of a legacy file
+struct LegacyReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    binary: bool,
+    display: &'a str,
+}
+
+impl<'a> LegacyReader<'a> {
+
+    //Return the next non-empty line
+    fn line(&mut self) -> Option<String> {
+        while self.pos < self.data.len() {
+            let end = self.data[self.pos..].iter().position(|b| *b == b'\n')
+                .map(|i| self.pos + i)
+                .unwrap_or(self.data.len());
+            let line = String::from_utf8_lossy(&self.data[self.pos..end]).trim().to_string();
+            self.pos = end + 1;
+            if !line.is_empty() {
+                return Some(line);
+            }
+        }
+        None
+    }
+
+    //Read count values of the given data type
+    fn values(&mut self, count: usize, dtype: &str) -> Vec<f64> {
+        if self.binary {
+            let size = count * type_size(dtype, self.display);
+            if self.pos + size > self.data.len() {
+                panic!("Unexpected end of data in {}!", self.display);
+            }
+            let values = decode(&self.data[self.pos..self.pos + size], dtype, true, self.display);
+            self.pos += size;
+            return values;
+        }
+
+        let mut values = Vec::with_capacity(count);
+        while values.len() < count {
+            while self.pos < self.data.len() && self.data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            while self.pos < self.data.len() && !self.data[self.pos].is_ascii_whitespace() {
+                self.pos += 1;
+            }
+            if start == self.pos {
+                panic!("Unexpected end of data in {}!", self.display);
+            }
+            let token = String::from_utf8_lossy(&self.data[start..self.pos]);
+            values.push(match token.parse() {
+                Ok(v) => v,
+                Err(_) => panic!("Could not parse {} in {}!", token, self.display),
+            });
+        }
+        values
+    }
+
+    //Skip a METADATA block, it ends with an empty line
+    fn skip_metadata(&mut self) {
+        while self.pos < self.data.len() {
+            let end = self.data[self.pos..].iter().position(|b| *b == b'\n')
+                .map(|i| self.pos + i)
+                .unwrap_or(self.data.len());
+            let empty = self.data[self.pos..end].iter().all(|b| b.is_ascii_whitespace());
+            self.pos = end + 1;
+            if empty {
+                break;
+            }
+        }
+    }
+
+    //Read the cells of a section (LINES, POLYGONS, ...), either as "n size" list or as OFFSETS/CONNECTIVITY
+    fn cells(&mut self, n: usize, size: usize) -> Vec<Vec<usize>> {
+        let mut cells = Vec::with_capacity(n);
+        let start = self.pos;
+
+        //Version 5 files store offsets and connectivity arrays
+        if let Some(line) = self.line() {
+            if line.starts_with("OFFSETS") {
+                let offsets = self.values(n, line.split_whitespace().nth(1).unwrap_or("vtktypeint64"));
+                let line = self.line().unwrap_or_default();
+                if !line.starts_with("CONNECTIVITY") {
+                    panic!("Missing connectivity in {}!", self.display);
+                }
+                let connectivity = self.values(size, line.split_whitespace().nth(1).unwrap_or("vtktypeint64"));
+                for o in offsets.windows(2) {
+                    cells.push(connectivity[o[0] as usize..o[1] as usize].iter().map(|i| *i as usize).collect());
+                }
+                return cells;
+            }
+        }
+        self.pos = start;
+
+        let values = self.values(size, "int");
+        let mut i = 0;
+        while cells.len() < n && i < values.len() {
+            let length = values[i] as usize;
+            if i + 1 + length > values.len() {
+                panic!("Wrong cell size in {}!", self.display);
+            }
+            cells.push(values[i + 1..i + 1 + length].iter().map(|v| *v as usize).collect());
+            i += 1 + length;
+        }
+        cells
+    }
+
+    //Read the arrays of a POINT_DATA or CELL_DATA section with count tuples
+    fn attributes(&mut self, count: usize, arrays: &mut Vec<DataArray>) {
+        loop {
+            let start = self.pos;
+            let line = match self.line() {
+                Some(l) => l,
+                None => return,
+            };
+            let parts: Vec<&str> = line.split_whitespace().collect();
+
+            match parts[0] {
+                "SCALARS" if parts.len() >= 3 => {
+                    let dim = parts.get(3).and_then(|d| d.parse().ok()).unwrap_or(1);
+                    //The lookup table line is optional
+                    let table_start = self.pos;
+                    if !self.line().map(|l| l.starts_with("LOOKUP_TABLE")).unwrap_or(false) {
+                        self.pos = table_start;
+                    }
+                    let data = self.values(count * dim, parts[2]);
+                    arrays.push(DataArray{ name: parts[1].to_string(), dim, data: data.iter().map(|v| *v as f32).collect() });
+                },
+                "VECTORS" | "NORMALS" if parts.len() >= 3 => {
+                    let data = self.values(count * 3, parts[2]);
+                    arrays.push(DataArray{ name: parts[1].to_string(), dim: 3, data: data.iter().map(|v| *v as f32).collect() });
+                },
+                "TENSORS" if parts.len() >= 3 => {
+                    let data = self.values(count * 9, parts[2]);
+                    arrays.push(DataArray{ name: parts[1].to_string(), dim: 9, data: data.iter().map(|v| *v as f32).collect() });
+                },
+                "TEXTURE_COORDINATES" if parts.len() >= 4 => {
+                    let dim = parts[2].parse().unwrap_or(1);
+                    let data = self.values(count * dim, parts[3]);
+                    arrays.push(DataArray{ name: parts[1].to_string(), dim, data: data.iter().map(|v| *v as f32).collect() });
+                },
+                "COLOR_SCALARS" if parts.len() >= 3 => {
+                    let dim = parts[2].parse().unwrap_or(1);
+                    let dtype = if self.binary { "unsigned_char" } else { "float" };
+                    let data = self.values(count * dim, dtype);
+                    arrays.push(DataArray{ name: parts[1].to_string(), dim, data: data.iter().map(|v| *v as f32).collect() });
+                },
+                "FIELD" if parts.len() >= 3 => {
+                    let n: usize = parts[2].parse().unwrap_or(0);
+                    for _ in 0..n {
+                        let line = self.line().unwrap_or_default();
+                        let field: Vec<&str> = line.split_whitespace().collect();
+                        if field.len() < 4 {
+                            panic!("Wrong field array {} in {}!", line, self.display);
+                        }
+                        let dim: usize = field[1].parse().unwrap_or(1);
+                        let tuples: usize = field[2].parse().unwrap_or(0);
+                        let data = self.values(dim * tuples, field[3]);
+                        if tuples == count {
+                            arrays.push(DataArray{ name: field[0].to_string(), dim, data: data.iter().map(|v| *v as f32).collect() });
+                        }
+                    }
+                },
+                "METADATA" => self.skip_metadata(),
+                _ => {
+                    //End of the section
+                    self.pos = start;
+                    return;
+                },
+            }
+        }
+    }
+}
+
+fn read_legacy(in_data: &[u8], display: &str) -> PolyData {
+    let mut reader = LegacyReader{ data: in_data, pos: 0, binary: false, display };
+    let mut poly = PolyData {
+        points: Vec::new(),
+        lines: Vec::new(),
+        point_data: Vec::new(),
+        cell_data: Vec::new(),
+    };
+
+    //Header: version, title, ASCII/BINARY and data set type
+    reader.line();
+    let title = reader.line().unwrap_or_default();
+    reader.binary = match reader.line().as_deref() {
+        Some("ASCII") => false,
+        Some("BINARY") => true,
+        _ => panic!("Wrong header in {}!", display),
+    };
+    if reader.line().map(|l| l.split_whitespace().nth(1) != Some("POLYDATA")).unwrap_or(true) {
+        panic!("{} does not contain PolyData!", display);
+    }
+
+    //Cells are numbered in the order vertices, lines, polygons, strips
+    let mut vertex_cells = 0;
+    let mut cell_data: Vec<DataArray> = Vec::new();
+
+    while let Some(line) = reader.line() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> usize { parts.get(i).and_then(|n| n.parse().ok()).unwrap_or(0) };
+
+        match parts[0] {
+            "POINTS" => {
+                let values = reader.values(3 * number(1), parts.get(2).cloned().unwrap_or("float"));
+                poly.points = values.chunks(3).map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]).collect();
+            },
+            "LINES" => poly.lines = reader.cells(number(1), number(2)),
+            "VERTICES" => vertex_cells = reader.cells(number(1), number(2)).len(),
+            "POLYGONS" | "TRIANGLE_STRIPS" => { reader.cells(number(1), number(2)); },
+            "POINT_DATA" => {
+                let count = number(1);
+                reader.attributes(count, &mut poly.point_data);
+            },
+            "CELL_DATA" => {
+                let count = number(1);
+                reader.attributes(count, &mut cell_data);
+            },
+            "METADATA" => reader.skip_metadata(),
+            _ => panic!("Unexpected section {} in {}!", parts[0], display),
+        }
+    }
+
+    //Keep the cell data of the lines only
+    let lines = poly.lines.len();
+    for array in cell_data.into_iter() {
+        if array.data.len() < (vertex_cells + lines) * array.dim {
+            panic!("Wrong size of cell data {} in {}!", array.name, display);
+        }
+        let data = array.data[vertex_cells * array.dim..(vertex_cells + lines) * array.dim].to_vec();
+        poly.cell_data.push(DataArray{ name: array.name, dim: array.dim, data });
+    }
+
+    //Slicer stores the coordinate system in the title
+    if title.contains("SPACE=LPS") {
+        flip_lps(&mut poly.points);
+    }
+
+    poly
+}
+
+//Replace characters that would break the whitespace separated legacy format
+fn legacy_name(name: &str) -> String {
+    name.replace(|c: char| c.is_whitespace(), "_")
+}
+
+fn write_legacy_field(out: &mut Vec<u8>, arrays: &[DataArray], count: usize) {
+    out.extend_from_slice(format!("FIELD FieldData {}\n", arrays.len()).as_bytes());
+    for array in arrays.iter() {
+        out.extend_from_slice(format!("{} {} {} float\n", legacy_name(&array.name), array.dim, count).as_bytes());
+        for v in array.data.iter() {
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        out.push(b'\n');
+    }
+}
+
+fn write_legacy(poly: &PolyData) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::new();
+
+    out.extend_from_slice(b"# vtk DataFile Version 4.2\nwmparc SPACE=RAS\nBINARY\nDATASET POLYDATA\n");
+
+    //Write points
+    out.extend_from_slice(format!("POINTS {} float\n", poly.points.len()).as_bytes());
+    for p in poly.points.iter() {
+        for coord in p.iter() {
+            out.extend_from_slice(&coord.to_be_bytes());
+        }
+    }
+    out.push(b'\n');
+
+    //Write lines
+    let size: usize = poly.lines.iter().map(|l| l.len() + 1).sum();
+    out.extend_from_slice(format!("LINES {} {}\n", poly.lines.len(), size).as_bytes());
+    for line in poly.lines.iter() {
+        out.extend_from_slice(&(line.len() as i32).to_be_bytes());
+        for i in line.iter() {
+            out.extend_from_slice(&(*i as i32).to_be_bytes());
+        }
+    }
+    out.push(b'\n');
+
+    //Write point and cell data as field arrays, they can have any number of components
+    if !poly.point_data.is_empty() {
+        out.extend_from_slice(format!("POINT_DATA {}\n", poly.points.len()).as_bytes());
+        write_legacy_field(&mut out, &poly.point_data, poly.points.len());
+    }
+    if !poly.cell_data.is_empty() {
+        out.extend_from_slice(format!("CELL_DATA {}\n", poly.lines.len()).as_bytes());
+        write_legacy_field(&mut out, &poly.cell_data, poly.lines.len());
+    }
+
+    out
+}
+
+/*
+ XML format
+*/
+
+const BASE64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+//Decode base64 in groups of four characters, so concatenated streams (header and data) are decoded as well
+fn base64_decode(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut group = [0u8; 4];
+    let mut n = 0;
+
+    for c in text.iter().filter(|c| !c.is_ascii_whitespace()) {
+        group[n] = *c;
+        n += 1;
+        if n < 4 {
+            continue;
+        }
+        n = 0;
+
+        let mut bits = 0u32;
+        let mut valid = 0;
+        for g in group.iter() {
+            bits <<= 6;
+            if let Some(v) = BASE64.iter().position(|b| b == g) {
+                bits |= v as u32;
+                valid += 1;
+            }
+        }
+        let bytes = [(bits >> 16) as u8, (bits >> 8) as u8, bits as u8];
+        out.extend_from_slice(&bytes[..valid.max(1) - 1]);
+    }
+    out
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut out = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let mut bits = 0u32;
+        for (i, b) in chunk.iter().enumerate() {
+            bits |= (*b as u32) << (16 - 8 * i);
+        }
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+//Return the value of an attribute of an XML tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=\"", name);
+    let mut pos = 0;
+    while let Some(i) = tag[pos..].find(&pattern) {
+        let start = pos + i;
+        pos = start + pattern.len();
+        //Make sure we did not find the end of another attribute name
+        if tag[..start].ends_with(|c: char| c.is_whitespace()) {
+            let end = tag[pos..].find('"')?;
+            return Some(tag[pos..pos + end].to_string());
+        }
+    }
+    None
+}
+
+//Global settings of a XML file to decode binary data
+struct XmlEncoding {
+    big_endian: bool,
+    header_type: String,
+    compressed: bool,
+}
+
+//Decode a binary block (header with the number of bytes followed by the data, optionally zlib compressed)
+fn decode_block(block: &[u8], dtype: &str, encoding: &XmlEncoding, display: &str) -> (Vec<f64>, usize) {
+    let header_size = type_size(&encoding.header_type, display);
+    let header_value = |i: usize| -> usize {
+        if block.len() < (i + 1) * header_size {
+            panic!("Unexpected end of data in {}!", display);
+        }
+        decode(&block[i * header_size..(i + 1) * header_size], &encoding.header_type, encoding.big_endian, display)[0] as usize
+    };
+
+    if !encoding.compressed {
+        let size = header_value(0);
+        let end = header_size + size;
+        if end > block.len() {
+            panic!("Unexpected end of data in {}!", display);
+        }
+        return (decode(&block[header_size..end], dtype, encoding.big_endian, display), end);
+    }
+
+    //Compressed header: number of blocks, block size, size of the last block, compressed size of every block
+    let blocks = header_value(0);
+    let mut pos = (3 + blocks) * header_size;
+    let mut data = Vec::new();
+    for b in 0..blocks {
+        let size = header_value(3 + b);
+        if pos + size > block.len() {
+            panic!("Unexpected end of data in {}!", display);
+        }
+        if let Err(why) = ZlibDecoder::new(&block[pos..pos + size]).read_to_end(&mut data) {
+            panic!("Could not decompress data of {}: {}", display, why);
+        }
+        pos += size;
+    }
+    (decode(&data, dtype, encoding.big_endian, display), pos)
+}
+
+fn read_xml(in_data: &[u8], display: &str) -> PolyData {
+    let mut poly = PolyData {
+        points: Vec::new(),
+        lines: Vec::new(),
+        point_data: Vec::new(),
+        cell_data: Vec::new(),
+    };
+
+    //Appended raw data can contain any byte, so only the part before it is parsed as XML
+    let appended = in_data.windows(13).position(|w| w == b"<AppendedData");
+    let xml_end = appended.unwrap_or(in_data.len());
+    let text = String::from_utf8_lossy(&in_data[..xml_end]).into_owned();
+
+    let appended_data: &[u8] = match appended {
+        Some(start) => {
+            let tag_end = match in_data[start..].iter().position(|b| *b == b'>') {
+                Some(i) => start + i + 1,
+                None => panic!("Wrong appended data in {}!", display),
+            };
+            let tag = String::from_utf8_lossy(&in_data[start..tag_end]).into_owned();
+            if attribute(&tag, "encoding").map(|e| e != "raw").unwrap_or(false) {
+                panic!("Only raw appended data is supported in {}!", display);
+            }
+            //The data starts after an underscore
+            match in_data[tag_end..].iter().position(|b| *b == b'_') {
+                Some(i) => &in_data[tag_end + i + 1..],
+                None => panic!("Wrong appended data in {}!", display),
+            }
+        },
+        None => &[],
+    };
+
+    let file_tag = match (text.find("<VTKFile"), text.find("<VTKFile").and_then(|s| text[s..].find('>').map(|e| s + e))) {
+        (Some(s), Some(e)) => text[s..e].to_string(),
+        _ => panic!("{} is not a VTK file!", display),
+    };
+    if attribute(&file_tag, "type").as_deref() != Some("PolyData") {
+        panic!("{} does not contain PolyData!", display);
+    }
+    let encoding = XmlEncoding {
+        big_endian: attribute(&file_tag, "byte_order").as_deref() == Some("BigEndian"),
+        header_type: attribute(&file_tag, "header_type").unwrap_or_else(|| "UInt32".to_string()),
+        compressed: attribute(&file_tag, "compressor").is_some(),
+    };
+
+    //Walk through the tags and remember the section of every data array
+    let mut section = String::new();
+    let mut connectivity: Vec<usize> = Vec::new();
+    let mut offsets: Vec<usize> = Vec::new();
+    let mut vertex_cells = 0;
+    let mut cell_data: Vec<DataArray> = Vec::new();
+    let mut pos = 0;
+
+    while let Some(start) = text[pos..].find('<').map(|i| pos + i) {
+        let end = match text[start..].find('>') {
+            Some(i) => start + i,
+            None => break,
+        };
+        let tag = text[start..end].to_string();
+        pos = end + 1;
+
+        let tag_name = tag[1..].split_whitespace().next().unwrap_or("").trim_end_matches('/').to_string();
+        match tag_name.as_str() {
+            "Piece" => {
+                vertex_cells = attribute(&tag, "NumberOfVerts").and_then(|n| n.parse().ok()).unwrap_or(0);
+            },
+            "PointData" | "CellData" | "Points" | "Lines" | "Verts" | "Polys" | "Strips" | "FieldData"
+                if !tag.ends_with('/') => section = tag_name.clone(),
+            "DataArray" => {
+                let dtype = attribute(&tag, "type").unwrap_or_else(|| "Float32".to_string());
+                let name = attribute(&tag, "Name").unwrap_or_default();
+                let dim = attribute(&tag, "NumberOfComponents").and_then(|n| n.parse().ok()).unwrap_or(1);
+                let format = attribute(&tag, "format").unwrap_or_else(|| "ascii".to_string());
+
+                //Inline content up to the closing tag
+                let content_end = if tag.ends_with('/') {
+                    pos
+                } else {
+                    text[pos..].find("</DataArray").map(|i| pos + i).unwrap_or(text.len())
+                };
+                let content = &text[pos..content_end];
+                pos = content_end;
+
+                let values = match format.as_str() {
+                    "ascii" => content.split_whitespace().map(|v| match v.parse() {
+                        Ok(v) => v,
+                        Err(_) => panic!("Could not parse {} in {}!", v, display),
+                    }).collect(),
+                    "binary" => decode_block(&base64_decode(content.as_bytes()), &dtype, &encoding, display).0,
+                    "appended" => {
+                        let offset: usize = attribute(&tag, "offset").and_then(|o| o.parse().ok()).unwrap_or(0);
+                        if offset > appended_data.len() {
+                            panic!("Wrong offset of {} in {}!", name, display);
+                        }
+                        decode_block(&appended_data[offset..], &dtype, &encoding, display).0
+                    },
+                    _ => panic!("Unsupported format {} in {}!", format, display),
+                };
+
+                match (section.as_str(), name.as_str()) {
+                    ("Points", _) => {
+                        poly.points = values.chunks(3).filter(|p| p.len() == 3)
+                            .map(|p| [p[0] as f32, p[1] as f32, p[2] as f32]).collect();
+                    },
+                    ("Lines", "connectivity") => connectivity = values.iter().map(|v| *v as usize).collect(),
+                    ("Lines", "offsets") => offsets = values.iter().map(|v| *v as usize).collect(),
+                    ("PointData", _) => poly.point_data.push(DataArray{ name, dim, data: values.iter().map(|v| *v as f32).collect() }),
+                    ("CellData", _) => cell_data.push(DataArray{ name, dim, data: values.iter().map(|v| *v as f32).collect() }),
+                    _ => {},
+                }
+            },
+            _ => {},
+        }
+    }
+
+    //The offsets point behind the last point of every line
+    let mut line_start = 0;
+    for end in offsets.iter() {
+        if *end < line_start || *end > connectivity.len() {
+            panic!("Wrong line offsets in {}!", display);
+        }
+        poly.lines.push(connectivity[line_start..*end].to_vec());
+        line_start = *end;
+    }
+
+    //Keep the cell data of the lines only
+    let lines = poly.lines.len();
+    for array in cell_data.into_iter() {
+        if array.data.len() < (vertex_cells + lines) * array.dim {
+            panic!("Wrong size of cell data {} in {}!", array.name, display);
+        }
+        let data = array.data[vertex_cells * array.dim..(vertex_cells + lines) * array.dim].to_vec();
+        poly.cell_data.push(DataArray{ name: array.name, dim: array.dim, data });
+    }
+
+    //Slicer stores the coordinate system in a comment
+    if text.contains("SPACE=LPS") {
+        flip_lps(&mut poly.points);
+    }
+
+    poly
+}
+
+//Replace characters that are not allowed in XML attributes
+fn xml_name(name: &str) -> String {
+    name.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+//Write a data array as base64 encoded binary block with UInt64 header
+fn write_xml_array(out: &mut String, dtype: &str, name: &str, dim: usize, data: Vec<u8>) {
+    let mut block = (data.len() as u64).to_le_bytes().to_vec();
+    block.extend_from_slice(&data);
+
+    out.push_str(&format!("        <DataArray type=\"{}\" Name=\"{}\" NumberOfComponents=\"{}\" format=\"binary\">\n          ",
+                          dtype, xml_name(name), dim));
+    out.push_str(&base64_encode(&block));
+    out.push_str("\n        </DataArray>\n");
+}
+
+fn write_xml(poly: &PolyData) -> Vec<u8> {
+    let mut out = String::new();
+
+    out.push_str("<?xml version=\"1.0\"?>\n<!-- wmparc SPACE=RAS -->\n");
+    out.push_str("<VTKFile type=\"PolyData\" version=\"1.0\" byte_order=\"LittleEndian\" header_type=\"UInt64\">\n");
+    out.push_str("  <PolyData>\n");
+    out.push_str(&format!("    <Piece NumberOfPoints=\"{}\" NumberOfVerts=\"0\" NumberOfLines=\"{}\" NumberOfStrips=\"0\" NumberOfPolys=\"0\">\n",
+                          poly.points.len(), poly.lines.len()));
+
+    //Write point and cell data
+    out.push_str("      <PointData>\n");
+    for array in poly.point_data.iter() {
+        write_xml_array(&mut out, "Float32", &array.name, array.dim,
+                        array.data.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect());
+    }
+    out.push_str("      </PointData>\n      <CellData>\n");
+    for array in poly.cell_data.iter() {
+        write_xml_array(&mut out, "Float32", &array.name, array.dim,
+                        array.data.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect());
+    }
+    out.push_str("      </CellData>\n");
+
+    //Write points
+    out.push_str("      <Points>\n");
+    write_xml_array(&mut out, "Float32", "Points", 3,
+                    poly.points.iter().flat_map(|p| p.iter().flat_map(|v| v.to_le_bytes().to_vec()).collect::<Vec<u8>>()).collect());
+    out.push_str("      </Points>\n");
+
+    //Write lines as connectivity and offsets (end of every line)
+    let mut connectivity: Vec<u8> = Vec::new();
+    let mut offsets: Vec<u8> = Vec::new();
+    let mut end = 0i64;
+    for line in poly.lines.iter() {
+        for i in line.iter() {
+            connectivity.extend_from_slice(&(*i as i64).to_le_bytes());
+        }
+        end += line.len() as i64;
+        offsets.extend_from_slice(&end.to_le_bytes());
+    }
+    out.push_str("      <Lines>\n");
+    write_xml_array(&mut out, "Int64", "connectivity", 1, connectivity);
+    write_xml_array(&mut out, "Int64", "offsets", 1, offsets);
+    out.push_str("      </Lines>\n");
+
+    out.push_str("    </Piece>\n  </PolyData>\n</VTKFile>\n");
+    out.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use test_util::temp_file;
+
+    fn test_poly() -> PolyData {
+        let mut poly = from_streamlines(&[
+            vec![[88.0, -122.0, -66.0], [86.5, -121.25, -65.125]],
+            vec![[82.0, -116.0, -60.0]],
+            vec![[-1.5, 0.0, 2.25], [0.0, 0.0, 0.0], [3.0, 4.0, 5.0]],
+        ]);
+        poly.point_data.push(DataArray { name: String::from("fa"), dim: 1, data: vec![0.5, 0.25, 0.75, 0.125, 1.0, 0.0] });
+        poly.cell_data.push(DataArray { name: String::from("cortex_label"), dim: 1, data: vec![1003.0, 2005.0, 0.0] });
+        poly.cell_data.push(DataArray { name: String::from("color"), dim: 3, data: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0] });
+        poly
+    }
+
+    fn assert_same(a: &PolyData, b: &PolyData) {
+        assert_eq!(a.points, b.points);
+        assert_eq!(a.lines, b.lines);
+        for (x, y) in a.point_data.iter().zip(b.point_data.iter()).chain(a.cell_data.iter().zip(b.cell_data.iter())) {
+            assert_eq!(x.name, y.name);
+            assert_eq!(x.dim, y.dim);
+            assert_eq!(x.data, y.data);
+        }
+        assert_eq!(a.point_data.len(), b.point_data.len());
+        assert_eq!(a.cell_data.len(), b.cell_data.len());
+    }
+
+    #[test]
+    fn write_read_legacy() {
+        let poly = test_poly();
+        let file = temp_file("roundtrip", "vtk");
+        write(&poly, &file);
+        let read = read(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(read.points.len(), 6);
+        assert_same(&read, &poly);
+    }
+
+    #[test]
+    fn write_read_xml() {
+        let poly = test_poly();
+        let file = temp_file("roundtrip", "vtp");
+        write(&poly, &file);
+        let read = read(&file);
+        fs::remove_file(&file).unwrap();
+
+        assert_eq!(read.points.len(), 6);
+        assert_same(&read, &poly);
+    }
+
+    #[test]
+    fn select_keeps_the_data_of_the_lines() {
+        let selected = select(&test_poly(), &[2, 0]);
+        assert_eq!(selected.points, vec![[-1.5, 0.0, 2.25], [0.0, 0.0, 0.0], [3.0, 4.0, 5.0], [88.0, -122.0, -66.0], [86.5, -121.25, -65.125]]);
+        assert_eq!(selected.lines, vec![vec![0, 1, 2], vec![3, 4]]);
+        assert_eq!(selected.point_data[0].data, vec![0.125, 1.0, 0.0, 0.5, 0.25]);
+        assert_eq!(selected.cell_data[0].data, vec![0.0, 1003.0]);
+        assert_eq!(selected.cell_data[1].data, vec![0.0, 0.0, 1.0, 1.0, 0.0, 0.0]);
+    }
+}